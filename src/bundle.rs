@@ -0,0 +1,83 @@
+//! `--format bundle`: a single compressed file holding everything
+//! about a run (the findings, the files that were scanned, snippets
+//! and the categories used), so that a report can be passed around
+//! and read back without the source.
+//!
+//! The file is a deflated JSON object:
+//!
+//! ```text
+//! { "format": "unsafe_ls-bundle", "version": 1,
+//!   "rules": { "<category>": "<description>", ... },
+//!   "crates": [ { "crate": ..., "files": [...], "unsafes": [...] }, ... ] }
+//! ```
+
+use flate;
+use serialize::json::{Json, ToJson};
+use syntax::codemap::{CodeMap, Pos};
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+use visitor::NodeInfo;
+
+pub const VERSION: u64 = 1;
+
+fn object(fields: Vec<(&str, Json)>) -> Json {
+    Json::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+}
+
+/// The part of a bundle for one crate, with its `unsafes`.
+pub fn crate_bundle(cm: &CodeMap, crate_name: &str, unsafes: &[&NodeInfo],
+                    snippets: bool) -> Json {
+    // the crate's own files, not those imported for spans from
+    // other crates' macros.
+    let files = cm.files.borrow().iter()
+        .filter(|fm| !fm.is_imported() && !fm.name.starts_with("<"))
+        .map(|fm| fm.name.to_json())
+        .collect();
+
+    let unsafes = unsafes.iter().map(|info| {
+        let lo = cm.lookup_char_pos(info.span.lo);
+        let hi = cm.lookup_char_pos(info.span.hi);
+        let mut counts = BTreeMap::new();
+        for &(name, _, spans) in info.kinds().iter().filter(|k| !k.2.is_empty()) {
+            counts.insert(name.to_string(), spans.len().to_json());
+        }
+        let mut fields = vec![("file", lo.file.name.to_json()),
+                              ("line", lo.line.to_json()),
+                              ("col", (lo.col.to_usize() + 1).to_json()),
+                              ("end_line", hi.line.to_json()),
+                              ("end_col", (hi.col.to_usize() + 1).to_json()),
+                              ("kind", if info.is_fn {"fn"} else {"block"}.to_json()),
+                              ("item", info.item_path.to_json()),
+                              ("fingerprint", info.fingerprint(cm).to_json()),
+                              ("counts", Json::Object(counts)),
+                              ("complexity", info.complexity().to_json())];
+        if snippets {
+            if let Ok(text) = cm.span_to_snippet(info.span) {
+                fields.push(("snippet", text.to_json()));
+            }
+        }
+        object(fields)
+    }).collect();
+
+    object(vec![("crate", crate_name.to_json()),
+                ("files", Json::Array(files)),
+                ("unsafes", Json::Array(unsafes))])
+}
+
+/// Write the bundle of `crates` to `path`, with `rules` describing
+/// each category.
+pub fn write(path: &Path, rules: &[(String, String)], crates: Vec<Json>) -> io::Result<()> {
+    let rules = rules.iter().map(|&(ref name, ref desc)| (name.clone(), desc.to_json()))
+        .collect();
+    let bundle = object(vec![("format", "unsafe_ls-bundle".to_json()),
+                             ("version", VERSION.to_json()),
+                             ("rules", Json::Object(rules)),
+                             ("crates", Json::Array(crates))]);
+    let compressed = flate::deflate_bytes(bundle.to_string().as_bytes());
+    let mut file = try!(File::create(path));
+    file.write_all(&compressed)
+}
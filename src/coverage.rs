@@ -0,0 +1,119 @@
+//! Reading line coverage from LCOV tracefiles, as produced by
+//! `kcov`, `llvm-cov export -format=lcov`, etc.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+pub struct Coverage {
+    /// Execution count of each instrumented line, by absolute path of
+    /// the source file.
+    files: Vec<(PathBuf, HashMap<usize, u64>)>,
+    /// What relative paths are relative to.
+    cwd: PathBuf,
+}
+
+impl Coverage {
+    pub fn from_lcov(path: &Path) -> io::Result<Coverage> {
+        let mut contents = String::new();
+        try!(File::open(path).and_then(|mut f| f.read_to_string(&mut contents)));
+        Ok(Coverage::parse(&contents, &try!(env::current_dir())))
+    }
+
+    /// The coverage in the tracefile `lcov`, with relative paths
+    /// resolved against `cwd`.
+    fn parse(lcov: &str, cwd: &Path) -> Coverage {
+        let mut files = Vec::new();
+        for line in lcov.lines() {
+            if line.starts_with("SF:") {
+                files.push((resolve(cwd, Path::new(&line[3..])), HashMap::new()))
+            } else if line.starts_with("DA:") {
+                let mut parts = line[3..].split(',');
+                let n = parts.next().and_then(|s| s.parse::<usize>().ok());
+                let count = parts.next().and_then(|s| s.parse::<u64>().ok());
+                if let (Some(n), Some(count), Some(file)) = (n, count, files.last_mut()) {
+                    *file.1.entry(n).or_insert(0) += count
+                }
+            }
+        }
+        Coverage { files: files, cwd: cwd.to_path_buf() }
+    }
+
+    /// Whether any of the lines `lo..hi` (inclusive, 1-based) of
+    /// `file` were executed, or `None` if there's no data for them.
+    pub fn executed(&self, file: &str, lo: usize, hi: usize) -> Option<bool> {
+        // the tracefile usually has absolute paths, while the codemap
+        // has them as given on the command line; just the end of the
+        // path could be another crate's file of the same name.
+        let file = resolve(&self.cwd, Path::new(file));
+        let mut result = None;
+        for &(_, ref lines) in self.files.iter().filter(|f| f.0 == file) {
+            for (_, &count) in lines.iter().filter(|&(n, _)| lo <= *n && *n <= hi) {
+                if count > 0 {
+                    return Some(true)
+                }
+                result = Some(false)
+            }
+        }
+        result
+    }
+}
+
+/// `path` resolved against `base`, without any `.` or `..`.
+fn resolve(base: &Path, path: &Path) -> PathBuf {
+    let mut resolved = PathBuf::new();
+    for c in base.join(path).components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => { resolved.pop(); }
+            c => resolved.push(c.as_os_str()),
+        }
+    }
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Coverage;
+    use std::path::Path;
+
+    #[test]
+    fn from_lcov() {
+        let lcov = "TN:\n\
+                    SF:/work/krate/src/lib.rs\n\
+                    DA:1,0\n\
+                    DA:2,3\n\
+                    DA:2,1\n\
+                    end_of_record\n\
+                    SF:other/src/main.rs\n\
+                    DA:7,0\n\
+                    end_of_record\n";
+        let coverage = Coverage::parse(lcov, Path::new("/work/krate"));
+        assert_eq!(coverage.files.len(), 2);
+        assert_eq!(coverage.files[0].0, Path::new("/work/krate/src/lib.rs"));
+        assert_eq!(coverage.files[0].1[&2], 4);
+        assert_eq!(coverage.files[1].0, Path::new("/work/krate/other/src/main.rs"));
+        assert_eq!(coverage.files[1].1[&7], 0);
+    }
+
+    #[test]
+    fn executed() {
+        let lcov = "SF:/work/krate/src/lib.rs\n\
+                    DA:3,0\n\
+                    DA:4,2\n\
+                    DA:9,0\n\
+                    SF:/work/krate/vendor/dep/src/lib.rs\n\
+                    DA:20,5\n";
+        let coverage = Coverage::parse(lcov, Path::new("/work/krate"));
+        assert_eq!(coverage.executed("src/lib.rs", 1, 3), Some(false));
+        assert_eq!(coverage.executed("src/lib.rs", 3, 5), Some(true));
+        assert_eq!(coverage.executed("./src/../src/lib.rs", 4, 4), Some(true));
+        assert_eq!(coverage.executed("src/lib.rs", 10, 12), None);
+        // only the vendored file with the same name has line 20.
+        assert_eq!(coverage.executed("src/lib.rs", 20, 20), None);
+        assert_eq!(coverage.executed("/work/krate/src/lib.rs", 9, 9), Some(false));
+        assert_eq!(coverage.executed("src/main.rs", 1, 100), None);
+    }
+}
@@ -0,0 +1,105 @@
+//! Asking git about the history of the code being analysed.
+
+use std::process::Command;
+
+/// When line `line` (1-based) of `file` was last changed, in seconds
+/// since the epoch, according to `git blame`.
+pub fn line_time(file: &str, line: usize) -> Option<i64> {
+    let output = match Command::new("git")
+        .arg("blame").arg("--porcelain")
+        .arg("-L").arg(&format!("{},{}", line, line))
+        .arg("--").arg(file)
+        .output() {
+        Ok(ref o) if o.status.success() => o.stdout.clone(),
+        _ => return None
+    };
+    let output = String::from_utf8_lossy(&output);
+    output.lines()
+        .find(|l| l.starts_with("author-time "))
+        .and_then(|l| l["author-time ".len()..].trim().parse().ok())
+}
+
+/// The number of commits that have changed `file`.
+pub fn commit_count(file: &str) -> Option<usize> {
+    match Command::new("git")
+        .arg("log").arg("--follow").arg("--format=%H")
+        .arg("--").arg(file)
+        .output() {
+        Ok(ref o) if o.status.success() => {
+            Some(String::from_utf8_lossy(&o.stdout).lines().count())
+        }
+        _ => None
+    }
+}
+
+/// Whether `rev` names a commit.
+pub fn is_revision(rev: &str) -> bool {
+    Command::new("git")
+        .arg("rev-parse").arg("--verify").arg("--quiet")
+        .arg(&format!("{}^{{commit}}", rev))
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+/// The lines of `file` that have been added or changed since `rev`,
+/// as inclusive, 1-based `(first, last)` ranges. Lines deleted from
+/// between two others count as changing both of them.
+pub fn changed_lines(file: &str, rev: &str) -> Option<Vec<(usize, usize)>> {
+    let output = match Command::new("git")
+        .arg("diff").arg("--no-color").arg("-U0").arg(rev)
+        .arg("--").arg(file)
+        .output() {
+        Ok(ref o) if o.status.success() => o.stdout.clone(),
+        _ => return None
+    };
+    Some(hunks(&String::from_utf8_lossy(&output)))
+}
+
+/// The new-file line ranges of the hunks in the `-U0` diff `diff`.
+fn hunks(diff: &str) -> Vec<(usize, usize)> {
+    let mut changed = Vec::new();
+    // hunk headers look like `@@ -12,3 +14,5 @@`, where the count is
+    // omitted if it is 1, and 0 for a pure deletion.
+    for line in diff.lines().filter(|l| l.starts_with("@@ ")) {
+        let new = match line.split(' ').find(|w| w.starts_with("+")) {
+            Some(new) => &new[1..],
+            None => continue
+        };
+        let mut parts = new.splitn(2, ',');
+        let start = match parts.next().and_then(|s| s.parse::<usize>().ok()) {
+            Some(start) => start,
+            None => continue
+        };
+        let count = parts.next().map_or(Some(1), |s| s.parse::<usize>().ok()).unwrap_or(0);
+        if count > 0 {
+            changed.push((start, start + count - 1))
+        } else {
+            // the deletion was just after line `start` (which is 0 at
+            // the top of the file), e.g. of a check in an unsafe block.
+            changed.push((start, start + 1))
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::hunks;
+
+    #[test]
+    fn hunk_headers() {
+        let diff = "diff --git a/src/lib.rs b/src/lib.rs\n\
+                    --- a/src/lib.rs\n\
+                    +++ b/src/lib.rs\n\
+                    @@ -3 +3 @@ fn foo() {\n\
+                    -    a\n\
+                    +    b\n\
+                    @@ -10,0 +11,2 @@\n\
+                    +    c\n\
+                    +    d\n\
+                    @@ -20,3 +22,0 @@\n";
+        assert_eq!(hunks(diff), vec![(3, 3), (11, 12), (22, 23)]);
+        assert_eq!(hunks(""), vec![]);
+    }
+}
@@ -0,0 +1,57 @@
+//! Matching file paths against shell-style globs, for
+//! `--include-path` and `--exclude-path`.
+
+/// Whether `path` matches `pattern`, where `*` matches anything
+/// except `/`, `**` matches any number of whole directories (and
+/// everything, at the end) and `?` matches a single character other
+/// than `/`.
+pub fn matches(pattern: &str, path: &str) -> bool {
+    match_bytes(pattern.as_bytes(), path.as_bytes())
+}
+
+fn match_bytes(p: &[u8], s: &[u8]) -> bool {
+    match p.first() {
+        None => s.is_empty(),
+        Some(&b'*') if p.get(1) == Some(&b'*') => {
+            let rest = &p[2..];
+            let rest = if rest.first() == Some(&b'/') { &rest[1..] } else { rest };
+            // the rest starts at the beginning of a path component.
+            rest.is_empty() ||
+                (0..s.len() + 1)
+                .filter(|&i| i == 0 || s[i - 1] == b'/')
+                .any(|i| match_bytes(rest, &s[i..]))
+        }
+        Some(&b'*') => {
+            (0..s.len() + 1)
+                .take_while(|&i| i == 0 || s[i - 1] != b'/')
+                .any(|i| match_bytes(&p[1..], &s[i..]))
+        }
+        Some(&b'?') => !s.is_empty() && s[0] != b'/' && match_bytes(&p[1..], &s[1..]),
+        Some(&c) => !s.is_empty() && s[0] == c && match_bytes(&p[1..], &s[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::matches;
+
+    #[test]
+    fn star() {
+        assert!(matches("src/*.rs", "src/lib.rs"));
+        assert!(!matches("src/*.rs", "src/a/lib.rs"));
+        assert!(matches("src/?ib.rs", "src/lib.rs"));
+        assert!(!matches("src/?lib.rs", "src//lib.rs"));
+    }
+
+    #[test]
+    fn double_star() {
+        assert!(matches("**/gen.rs", "gen.rs"));
+        assert!(matches("**/gen.rs", "src/a/gen.rs"));
+        assert!(!matches("**/gen.rs", "src/codegen.rs"));
+        assert!(matches("**/tests/**", "src/tests/x.rs"));
+        assert!(!matches("**/tests/**", "src/mytests/x.rs"));
+        assert!(matches("src/**", "src/a/b.rs"));
+        assert!(matches("src/**/b.rs", "src/b.rs"));
+        assert!(!matches("src/**/b.rs", "lib/b.rs"));
+    }
+}
@@ -0,0 +1,28 @@
+//! The library behind `unsafe_ls`.
+//!
+//! The analysis itself uses the compiler's internals, so needs a
+//! nightly compiler, and is only included with the `engine` feature
+//! (on by default). Without it, the report model and formats, and the
+//! helpers for coverage, git history and lint levels, build on stable.
+
+#![cfg_attr(feature = "engine", feature(rustc_private, slice_patterns))]
+
+#[cfg(feature = "engine")] extern crate flate;
+#[cfg(feature = "engine")] extern crate rustc;
+#[cfg(feature = "engine")] extern crate serialize;
+#[cfg(feature = "engine")] extern crate syntax;
+
+pub mod coverage;
+pub mod git;
+pub mod glob;
+pub mod lints;
+pub mod report;
+pub mod roots;
+
+#[cfg(feature = "engine")] pub mod bundle;
+#[cfg(feature = "engine")] pub mod macros;
+#[cfg(feature = "engine")] pub mod position;
+#[cfg(feature = "engine")] pub mod sarif;
+#[cfg(feature = "engine")] pub mod scaffold;
+#[cfg(feature = "engine")] pub mod suppress;
+#[cfg(feature = "engine")] pub mod visitor;
@@ -0,0 +1,139 @@
+//! Finding the `unsafe_code` lint level a crate is configured with in
+//! its Cargo manifest's `[lints.rust]` table (or inherited from
+//! `[workspace.lints.rust]`).
+//!
+//! This only understands the simple forms, `unsafe_code = "forbid"`
+//! and `unsafe_code = { level = "forbid", ... }`, which is what
+//! manifests actually contain.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// The configured `unsafe_code` level for the crate with root
+/// `crate_root`, along with the manifest it came from. Manifests
+/// outside `boundary` (the project root, if any) aren't consulted.
+pub fn unsafe_code_level(crate_root: &Path,
+                         boundary: Option<&Path>) -> Option<(String, PathBuf)> {
+    let manifests = manifests_above(crate_root, boundary);
+    let (package, contents) = match manifests.first() {
+        Some(&(ref p, ref c)) => (p, c),
+        None => return None
+    };
+
+    if let Some(level) = find_level(contents, "lints.rust") {
+        return Some((level, package.clone()))
+    }
+    if !inherits_workspace(contents) {
+        return None
+    }
+    // the workspace root is the nearest manifest with a `[workspace]`,
+    // which may be the package's own.
+    manifests.iter()
+        .find(|&&(_, ref c)| c.lines().any(|l| section(l) == Some("workspace")))
+        .and_then(|&(ref p, ref c)| {
+            find_level(c, "workspace.lints.rust").map(|l| (l, p.clone()))
+        })
+}
+
+/// Every `Cargo.toml` in the directories containing `path`, innermost
+/// first, stopping at `boundary`.
+fn manifests_above(path: &Path, boundary: Option<&Path>) -> Vec<(PathBuf, String)> {
+    let mut manifests = Vec::new();
+    let mut dir = path.parent();
+    while let Some(d) = dir {
+        let manifest = d.join("Cargo.toml");
+        if let Some(contents) = read(&manifest) {
+            manifests.push((manifest, contents))
+        }
+        if Some(d) == boundary {
+            break
+        }
+        dir = d.parent();
+    }
+    manifests
+}
+
+fn read(path: &Path) -> Option<String> {
+    let mut s = String::new();
+    File::open(path).and_then(|mut f| f.read_to_string(&mut s)).ok().map(|_| s)
+}
+
+/// The name of the table started by `line`, if it is a header.
+fn section(line: &str) -> Option<&str> {
+    let line = line.trim();
+    if line.starts_with("[") && line.ends_with("]") && !line.starts_with("[[") {
+        Some(line[1..line.len() - 1].trim())
+    } else {
+        None
+    }
+}
+
+/// The `unsafe_code` value in the table `table`.
+fn find_level(manifest: &str, table: &str) -> Option<String> {
+    let mut current = "";
+    for line in manifest.lines() {
+        if let Some(s) = section(line) {
+            current = s;
+            continue
+        }
+        let mut parts = line.splitn(2, '=');
+        let (key, value) = match (parts.next(), parts.next()) {
+            (Some(k), Some(v)) => (k.trim(), v.trim()),
+            _ => continue
+        };
+        if current == table && key == "unsafe_code" {
+            // either `"level"` or `{ level = "level", priority = ... }`
+            let value = match value.find("level") {
+                Some(i) if value.starts_with("{") => &value[i..],
+                _ => value
+            };
+            return value.split('"').nth(1).map(|s| s.to_string())
+        }
+    }
+    None
+}
+
+/// Whether `[lints]` has `workspace = true`.
+fn inherits_workspace(manifest: &str) -> bool {
+    let mut current = "";
+    for line in manifest.lines() {
+        if let Some(s) = section(line) {
+            current = s;
+        } else if current == "lints" {
+            let words = line.split('=').map(|s| s.trim()).collect::<Vec<_>>();
+            if words == ["workspace", "true"] {
+                return true
+            }
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_level, inherits_workspace};
+
+    #[test]
+    fn levels() {
+        let manifest = "[package]\nname = \"foo\"\n\
+                        [lints.rust]\nunsafe_code = \"forbid\"\n";
+        assert_eq!(find_level(manifest, "lints.rust"), Some("forbid".to_string()));
+        assert_eq!(find_level(manifest, "workspace.lints.rust"), None);
+
+        let manifest = "[workspace.lints.rust]\n\
+                        unsafe_code = { level = \"deny\", priority = -1 }\n";
+        assert_eq!(find_level(manifest, "workspace.lints.rust"), Some("deny".to_string()));
+
+        // only in the given table.
+        let manifest = "[lints.clippy]\nunsafe_code = \"allow\"\n";
+        assert_eq!(find_level(manifest, "lints.rust"), None);
+    }
+
+    #[test]
+    fn workspace() {
+        assert!(inherits_workspace("[lints]\nworkspace = true\n"));
+        assert!(!inherits_workspace("[lints]\nworkspace = false\n"));
+        assert!(!inherits_workspace("[package]\nworkspace = true\n"));
+    }
+}
@@ -0,0 +1,67 @@
+//! Macros: a syntactic scan of exported `macro_rules!` definitions
+//! (unsafe code in a macro that this crate never expands itself
+//! doesn't appear in the expanded AST that everything else looks at),
+//! and working out where code produced by expansions came from.
+
+use syntax::ast;
+use syntax::codemap::{CodeMap, Span, NO_EXPANSION};
+use syntax::parse::token;
+
+/// Each `unsafe` in the body of an exported macro, as `(span, macro
+/// name)`.
+pub fn macro_unsafes(krate: &ast::Crate) -> Vec<(Span, String)> {
+    let mut found = Vec::new();
+    for def in &krate.exported_macros {
+        let name = token::get_ident(def.ident).to_string();
+        scan(&def.body, &name, &mut found);
+    }
+    found
+}
+
+fn scan(tts: &[ast::TokenTree], name: &str, found: &mut Vec<(Span, String)>) {
+    for tt in tts {
+        match *tt {
+            ast::TtToken(span, ref tok) => {
+                if tok.is_keyword(token::keywords::Unsafe) {
+                    found.push((span, name.to_string()))
+                }
+            }
+            ast::TtDelimited(_, ref delimited) => scan(&delimited.tts, name, found),
+            ast::TtSequence(_, ref seq) => scan(&seq.tts, name, found),
+        }
+    }
+}
+
+/// The span that `span` was ultimately expanded from, i.e. the
+/// outermost macro invocation that produced it (or itself, if it's
+/// not from a macro).
+pub fn call_site(cm: &CodeMap, span: Span) -> Span {
+    let mut span = span;
+    while span.expn_id != NO_EXPANSION {
+        match cm.with_expn_info(span.expn_id, |ei| ei.map(|ei| ei.call_site)) {
+            Some(call_site) => span = call_site,
+            None => break
+        }
+    }
+    span
+}
+
+/// Whether `span` came from expanding a macro defined in another
+/// crate.
+pub fn from_external_macro(cm: &CodeMap, span: Span) -> bool {
+    let mut span = span;
+    while span.expn_id != NO_EXPANSION {
+        let (call_site, def_site) = match cm.with_expn_info(span.expn_id, |ei| {
+            ei.map(|ei| (ei.call_site, ei.callee.span))
+        }) {
+            Some(sites) => sites,
+            None => return false
+        };
+        match def_site {
+            Some(def) if !cm.lookup_char_pos(def.lo).file.is_imported() => {}
+            _ => return true
+        }
+        span = call_site;
+    }
+    false
+}
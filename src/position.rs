@@ -0,0 +1,30 @@
+//! Converting spans to the line/column positions editors use.
+
+use syntax::codemap::{BytePos, CodeMap, Pos, Span};
+
+/// A zero-based line, and a column counted in UTF-16 code units, as
+/// in the Language Server Protocol.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Position {
+    pub line: usize,
+    pub character: usize,
+}
+
+/// The position of `pos`. Characters outside the Basic Multilingual
+/// Plane take two UTF-16 code units, so this differs from the
+/// compiler's (per-`char`) column when they appear earlier on the
+/// line.
+pub fn position(cm: &CodeMap, pos: BytePos) -> Position {
+    let loc = cm.lookup_char_pos(pos);
+    let text = loc.file.get_line(loc.line - 1).unwrap_or(String::new());
+    let character = text.chars().take(loc.col.to_usize()).fold(0, |a, c| a + c.len_utf16());
+    Position {
+        line: loc.line - 1,
+        character: character,
+    }
+}
+
+/// The start and end positions of `span`.
+pub fn range(cm: &CodeMap, span: Span) -> (Position, Position) {
+    (position(cm, span.lo), position(cm, span.hi))
+}
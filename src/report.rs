@@ -0,0 +1,220 @@
+//! The report model and its textual formats, independent of the
+//! analysis that produces it.
+
+use std::cmp;
+use std::env;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    Human,
+    /// Paths relative to the current directory, whitespace collapsed
+    /// and everything sorted by location, so that the output only
+    /// changes when the unsafe code does.
+    Snapshot,
+    /// Counts for each item containing unsafe code, keyed by the same
+    /// paths as rustdoc's search index, in an array with an object for
+    /// each crate.
+    Json,
+    /// Static Analysis Results Interchange Format, for code scanning
+    /// services.
+    Sarif,
+    /// A compressed file with the findings, scanned files, snippets
+    /// and categories, written to the `-o` file.
+    Bundle,
+    /// `file,line,actions` CSV rows.
+    Heatmap,
+}
+
+/// A summary line and the source lines that go with it.
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub file: String,
+    pub line: usize,
+    pub col: usize,
+    pub summary: String,
+    pub lines: Vec<String>,
+    /// Whether coverage data says this is never executed.
+    pub untested: bool,
+}
+
+/// Format `entries` as text, merging identical ones on the same line
+/// if `collapse`. The JSON and SARIF formats are built directly from
+/// the analysis, not from entries.
+pub fn render(format: Format, collapse: bool, mut entries: Vec<Entry>) -> String {
+    if collapse {
+        entries = collapse_entries(entries);
+    }
+    let mut out = String::new();
+    match format {
+        Format::Human => {
+            for e in &entries {
+                out.push_str(&format!("{}:{}:{}: {}\n", e.file, e.line, e.col, e.summary));
+                for line in &e.lines {
+                    out.push_str(line);
+                    out.push('\n');
+                }
+            }
+
+            let untested = entries.iter().filter(|e| e.untested).collect::<Vec<_>>();
+            if !untested.is_empty() {
+                out.push_str("\nuntested unsafe:\n");
+                for e in untested {
+                    out.push_str(&format!("    {}:{}:{}\n", e.file, e.line, e.col));
+                }
+            }
+        }
+        Format::Json | Format::Sarif | Format::Bundle | Format::Heatmap => {
+            panic!("{:?} isn't rendered from entries", format)
+        }
+        Format::Snapshot => {
+            let cwd = env::current_dir().unwrap();
+            for e in &mut entries {
+                if let Some(rel) = relative_to(Path::new(&e.file), &cwd) {
+                    e.file = rel.to_string_lossy().into_owned();
+                }
+            }
+            entries.sort_by(|a, b| {
+                (&a.file, a.line, a.col, &a.summary).cmp(&(&b.file, b.line, b.col, &b.summary))
+            });
+            for e in &entries {
+                out.push_str(&format!("{}:{}:{}: {}\n", e.file, e.line, e.col, e.summary));
+                for line in &e.lines {
+                    let words = line.split_whitespace().collect::<Vec<_>>();
+                    out.push_str(&format!("    {}\n", words.connect(" ")));
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Merge entries with the same summary on the same line, keeping the
+/// first and noting how many there were. (The exact spans are still
+/// in the JSON and SARIF output.)
+fn collapse_entries(entries: Vec<Entry>) -> Vec<Entry> {
+    let mut collapsed: Vec<(Entry, usize)> = Vec::new();
+    for e in entries {
+        match collapsed.iter().position(|&(ref c, _)| {
+            (&c.file, c.line, &c.summary) == (&e.file, e.line, &e.summary)
+        }) {
+            Some(i) => collapsed[i].1 += 1,
+            None => collapsed.push((e, 1)),
+        }
+    }
+    collapsed.into_iter().map(|(mut e, count)| {
+        if count > 1 {
+            e.summary.push_str(&format!(" (x{})", count));
+        }
+        e
+    }).collect()
+}
+
+/// `path` relative to `base`, if it's inside it.
+pub fn relative_to(path: &Path, base: &Path) -> Option<PathBuf> {
+    let mut components = path.components();
+    for c in base.components() {
+        if components.next() != Some(c) {
+            return None
+        }
+    }
+    Some(components.as_path().to_path_buf())
+}
+
+/// The lines only in `expected` (prefixed with `-`) and only in
+/// `actual` (prefixed with `+`), in order, for comparing snapshots.
+pub fn snapshot_diff(expected: &str, actual: &str) -> Vec<String> {
+    let old = expected.lines().collect::<Vec<_>>();
+    let new = actual.lines().collect::<Vec<_>>();
+    // `common[i][j]`: the length of the longest common subsequence
+    // of `old[i..]` and `new[j..]`.
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                cmp::max(common[i + 1][j], common[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut diff = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && common[i + 1][j] >= common[i][j + 1]) {
+            diff.push(format!("-{}", old[i]));
+            i += 1;
+        } else {
+            diff.push(format!("+{}", new[j]));
+            j += 1;
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{relative_to, render, snapshot_diff, Entry, Format};
+    use std::env;
+    use std::path::Path;
+
+    fn entry(file: &str, line: usize, summary: &str, lines: &[&str]) -> Entry {
+        Entry {
+            file: file.to_string(),
+            line: line,
+            col: 5,
+            summary: summary.to_string(),
+            lines: lines.iter().map(|l| l.to_string()).collect(),
+            untested: false,
+        }
+    }
+
+    #[test]
+    fn snapshot() {
+        let file = env::current_dir().unwrap().join("src").join("lib.rs");
+        let file = file.to_string_lossy();
+        let entries = vec![entry(&file, 9, "block with 1 ffi", &["        abort()"]),
+                           entry("/elsewhere/lib.rs", 1, "fn with 1 deref", &[]),
+                           entry(&file, 3, "block with 1 deref", &["    *p  =  1;"])];
+        assert_eq!(render(Format::Snapshot, false, entries),
+                   "/elsewhere/lib.rs:1:5: fn with 1 deref\n\
+                    src/lib.rs:3:5: block with 1 deref\n    *p = 1;\n\
+                    src/lib.rs:9:5: block with 1 ffi\n    abort()\n");
+    }
+
+    #[test]
+    fn collapse() {
+        let entries = vec![entry("a.rs", 1, "block with 1 ffi", &["x"]),
+                           entry("a.rs", 2, "block with 1 ffi", &[]),
+                           entry("a.rs", 1, "block with 1 deref", &[]),
+                           entry("a.rs", 1, "block with 1 ffi", &["y"])];
+        assert_eq!(render(Format::Human, true, entries),
+                   "a.rs:1:5: block with 1 ffi (x2)\nx\n\
+                    a.rs:2:5: block with 1 ffi\n\
+                    a.rs:1:5: block with 1 deref\n");
+    }
+
+    #[test]
+    fn relative() {
+        assert_eq!(relative_to(Path::new("/a/b/c.rs"), Path::new("/a")),
+                   Some(Path::new("b/c.rs").to_path_buf()));
+        assert_eq!(relative_to(Path::new("/a/b/c.rs"), Path::new("/a/b/c.rs")),
+                   Some(Path::new("").to_path_buf()));
+        assert_eq!(relative_to(Path::new("/ab/c.rs"), Path::new("/a")), None);
+        assert_eq!(relative_to(Path::new("b/c.rs"), Path::new("/a")), None);
+    }
+
+    #[test]
+    fn diff() {
+        assert_eq!(snapshot_diff("a\nb\nc\n", "a\nb\nc\n"), Vec::<String>::new());
+        assert_eq!(snapshot_diff("a\nb\nc\n", "a\nx\nc\nd\n"),
+                   vec!["-b", "+x", "+d"]);
+        // the same lines, but more of them, or in a different order.
+        assert_eq!(snapshot_diff("a\nb\n", "a\nb\nb\n"), vec!["+b"]);
+        assert_eq!(snapshot_diff("a\nb\n", "b\na\n"), vec!["-a", "+a"]);
+    }
+}
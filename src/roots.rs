@@ -0,0 +1,48 @@
+//! Finding the crates in a project root given with `--root`, e.g. one
+//! of several unrelated workspaces in a monorepo.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The crate roots (`src/lib.rs` and `src/main.rs`) of every package
+/// under `root`, in a consistent order.
+pub fn crate_roots(root: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    walk(root, &mut found);
+    found.sort();
+    found
+}
+
+fn walk(dir: &Path, found: &mut Vec<PathBuf>) {
+    if dir.join("Cargo.toml").is_file() {
+        for file in &["src/lib.rs", "src/main.rs"] {
+            let path = dir.join(file);
+            if path.is_file() {
+                found.push(path)
+            }
+        }
+    }
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        let skip = path.file_name().and_then(|n| n.to_str())
+            .map_or(true, |n| n.starts_with(".") || n == "target");
+        if !skip && path.is_dir() {
+            walk(&path, found)
+        }
+    }
+}
+
+/// The innermost of `roots` containing `path`, if any.
+pub fn root_of<'a>(path: &Path, roots: &'a [PathBuf]) -> Option<&'a Path> {
+    roots.iter()
+        .filter(|r| path.starts_with(r))
+        .fold(None, |inner: Option<&'a PathBuf>, r| match inner {
+            Some(i) if i.components().count() >= r.components().count() => Some(i),
+            _ => Some(r)
+        })
+        .map(|r| &**r)
+}
@@ -0,0 +1,108 @@
+//! SARIF 2.1.0 output, for code scanning services.
+
+use serialize::json::{Json, ToJson};
+use syntax::codemap::{CodeMap, Span};
+
+use std::collections::BTreeMap;
+
+use position;
+use visitor::NodeInfo;
+
+/// The number of lines either side of a block to include in its
+/// `contextRegion`.
+const CONTEXT_LINES: usize = 2;
+
+fn object(fields: Vec<(&str, Json)>) -> Json {
+    Json::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+}
+
+/// A SARIF region covering `span`, including the source text if
+/// `snippets`, along with a `contextRegion` covering a few lines
+/// either side.
+fn regions(cm: &CodeMap, span: Span, snippets: bool) -> Vec<(&'static str, Json)> {
+    let lo = cm.lookup_char_pos(span.lo);
+    let hi = cm.lookup_char_pos(span.hi);
+    // SARIF columns are UTF-16 code units by default.
+    let (from, to) = position::range(cm, span);
+
+    let mut region = vec![("startLine", (from.line + 1).to_json()),
+                          ("startColumn", (from.character + 1).to_json()),
+                          ("endLine", (to.line + 1).to_json()),
+                          ("endColumn", (to.character + 1).to_json())];
+    if !snippets {
+        return vec![("region", object(region))]
+    }
+    if let Ok(text) = cm.span_to_snippet(span) {
+        region.push(("snippet", object(vec![("text", text.to_json())])));
+    }
+
+    // lines are 1-based, `get_line` is 0-based.
+    let start = if lo.line > CONTEXT_LINES { lo.line - CONTEXT_LINES } else { 1 };
+    let mut end = hi.line + CONTEXT_LINES;
+    let mut context = String::new();
+    for line in start..end + 1 {
+        match lo.file.get_line(line - 1) {
+            Some(text) => {
+                context.push_str(&text);
+                context.push('\n');
+            }
+            None => {
+                end = line - 1;
+                break
+            }
+        }
+    }
+    let context = object(vec![("startLine", start.to_json()),
+                              ("endLine", end.to_json()),
+                              ("snippet", object(vec![("text", context.to_json())]))]);
+    vec![("region", object(region)), ("contextRegion", context)]
+}
+
+/// A SARIF run, for one crate, with a result for each of `unsafes`.
+pub fn run(cm: &CodeMap, unsafes: &[&NodeInfo], snippets: bool) -> Json {
+    let results = unsafes.iter().map(|info| {
+        let lo = cm.lookup_char_pos(info.span.lo);
+        let mut location = vec![("artifactLocation",
+                                 object(vec![("uri", lo.file.name.to_json())]))];
+        location.extend(regions(cm, info.span, snippets).into_iter());
+
+        let rule = if info.is_fn { "unsafe-fn" } else { "unsafe-block" };
+        let mut properties = BTreeMap::new();
+        for &(name, _, spans) in info.kinds().iter().filter(|k| !k.2.is_empty()) {
+            properties.insert(name.to_string(), spans.len().to_json());
+        }
+        properties.insert("complexity".to_string(), info.complexity().to_json());
+        object(vec![
+            ("ruleId", rule.to_json()),
+            ("level", "note".to_json()),
+            ("message", object(vec![("text", format!("{} with {:?}",
+                                                     if info.is_fn {"fn"} else {"block"},
+                                                     info).to_json())])),
+            ("locations", Json::Array(vec![
+                object(vec![("physicalLocation", object(location))])
+            ])),
+            ("partialFingerprints", object(vec![("unsafeBlock/v1",
+                                                 info.fingerprint(cm).to_json())])),
+            ("properties", Json::Object(properties)),
+        ])
+    }).collect();
+
+    let rules = ["unsafe-fn", "unsafe-block"].iter().map(|id| {
+        object(vec![("id", id.to_json())])
+    }).collect();
+    let driver = object(vec![("name", "unsafe_ls".to_json()),
+                             ("informationUri",
+                              "https://github.com/huonw/unsafe_ls".to_json()),
+                             ("rules", Json::Array(rules))]);
+    object(vec![("tool", object(vec![("driver", driver)])),
+                ("results", Json::Array(results))])
+}
+
+/// The SARIF log of `runs`, one for each crate analysed.
+pub fn log(runs: Vec<Json>) -> Json {
+    object(vec![
+        ("version", "2.1.0".to_json()),
+        ("$schema", "https://json.schemastore.org/sarif-2.1.0.json".to_json()),
+        ("runs", Json::Array(runs)),
+    ])
+}
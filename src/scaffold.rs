@@ -0,0 +1,65 @@
+//! `unsafe_ls scaffold-docs`: write a `SAFETY.md` next to each module
+//! with unsafe code, with a section for each unsafe block or fn to be
+//! filled in with why it is correct.
+
+use syntax::codemap::{CodeMap, Pos};
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use visitor::UnsafeVisitor;
+
+/// Write the skeletons for everything found by `visitor`, returning a
+/// description of what was done. Existing `SAFETY.md`s are left alone.
+pub fn scaffold_docs(cm: &CodeMap, visitor: &UnsafeVisitor) -> String {
+    // directory -> file -> sections
+    let mut docs = BTreeMap::new();
+    for (_, info) in visitor.unsafes.iter() {
+        if info.compiler { continue }
+
+        let lo = cm.lookup_char_pos_adj(info.span.lo);
+        let dir = Path::new(&lo.filename).parent().unwrap_or(Path::new("")).to_path_buf();
+        let section = format!("### {} at {}:{}:{}{}\n\n\
+                               Contains: {:?}\n\n\
+                               #### Invariants\n\n\
+                               #### Justification\n\n",
+                              if info.is_fn {"`unsafe fn`"} else {"`unsafe` block"},
+                              lo.filename, lo.line, lo.col.to_usize() + 1,
+                              if info.item_path.is_empty() {
+                                  String::new()
+                              } else {
+                                  format!(" in `{}`", info.item_path)
+                              },
+                              *info);
+        docs.entry(dir).or_insert(BTreeMap::new())
+            .entry(lo.filename.clone()).or_insert(Vec::new())
+            .push(section);
+    }
+
+    let mut out = String::new();
+    for (dir, files) in docs {
+        let path = dir.join("SAFETY.md");
+        if path.exists() {
+            out.push_str(&format!("{}: already exists, skipping\n", path.display()));
+            continue
+        }
+        let mut text = "# Safety\n\n".to_string();
+        for (file, sections) in files {
+            text.push_str(&format!("## {}\n\n", file));
+            for section in sections {
+                text.push_str(&section);
+            }
+        }
+        match write(&path, &text) {
+            Ok(()) => out.push_str(&format!("{}: written\n", path.display())),
+            Err(e) => out.push_str(&format!("{}: couldn't write: {}\n", path.display(), e)),
+        }
+    }
+    out
+}
+
+fn write(path: &PathBuf, text: &str) -> ::std::io::Result<()> {
+    try!(File::create(path)).write_all(text.as_bytes())
+}
@@ -0,0 +1,39 @@
+//! Source-level suppression of audited `unsafe`s, by a `// unsafe_ls:
+//! ignore` comment or a `#[cfg_attr(unsafe_ls, allow(unsafe_ls))]`
+//! attribute on (or just before) the block or function.
+//!
+//! `cfg_attr`s are stripped before the analysis sees anything, so
+//! both are found by looking at the source text.
+
+use syntax::codemap::{CodeMap, Span};
+
+const MARKERS: &'static [&'static str] = &["unsafe_ls: ignore", "cfg_attr(unsafe_ls"];
+
+/// Whether the `unsafe` at `span` has been marked as suppressed: on
+/// its first line, or in the run of attributes and comments
+/// immediately above it.
+pub fn suppressed(cm: &CodeMap, span: Span) -> bool {
+    let loc = cm.lookup_char_pos(span.lo);
+    let marked = |line: &str| MARKERS.iter().any(|m| line.contains(m));
+
+    // `Loc::line` is 1-based, `get_line` is 0-based.
+    let mut idx = loc.line - 1;
+    match loc.file.get_line(idx) {
+        Some(ref line) if marked(line) => return true,
+        Some(_) => {}
+        None => return false
+    }
+    while idx > 0 {
+        idx -= 1;
+        let line = match loc.file.get_line(idx) {
+            Some(line) => line,
+            None => return false
+        };
+        let trimmed = line.trim_left();
+        if !trimmed.starts_with("#[") && !trimmed.starts_with("//") {
+            return false
+        }
+        if marked(trimmed) { return true }
+    }
+    false
+}
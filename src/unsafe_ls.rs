@@ -2,25 +2,51 @@
 #![feature(rustc_private, slice_patterns)]
 extern crate arena;
 extern crate getopts;
+extern crate regex;
 extern crate syntax;
 extern crate rustc;
 extern crate rustc_back;
 extern crate rustc_driver;
 extern crate rustc_trans;
 extern crate rustc_typeck;
+extern crate serialize;
+extern crate time;
+extern crate unsafe_ls;
 
 use rustc::session::{self, config};
 use rustc_driver::driver;
+use rustc::middle::privacy::ExportedItems;
 use rustc::middle::ty;
 use rustc::session::search_paths::SearchPaths;
-use syntax::codemap::Pos;
-use std::collections::{HashSet, HashMap};
+use rustc_trans::back::link;
+use regex::Regex;
+use serialize::json::{self, Json, ToJson};
+use syntax::codemap::{CodeMap, Pos, Span};
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet, HashSet, HashMap};
 use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, Read, Write};
+use std::process;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::thread;
 use std::path::{Path, PathBuf};
 
-mod visitor;
+use unsafe_ls::{bundle, coverage, git, glob, lints, macros, roots, sarif, scaffold, suppress,
+                visitor};
+use unsafe_ls::report::{self, Entry, Format};
+
+/// The flags turned on by each `--profile`.
+static NO_PROFILE: &'static [&'static str] = &[];
+static MINIMAL_PROFILE: &'static [&'static str] = &["nonffi"];
+static DEFAULT_PROFILE: &'static [&'static str] = &["nonffi", "ffi"];
+static STRICT_PROFILE: &'static [&'static str] = &[
+    "nonffi", "ffi", "verbose", "extern-items", "exports", "thin-wrappers", "attrs",
+    "lint-anomalies", "extended", "unused", "static-muts", "unsafe-traits", "target-features",
+    "macros",
+];
 
 static DEFAULT_LIB_DIR: &'static str = "/usr/local/lib/rustlib/x86_64-unknown-linux-gnu/lib";
 
@@ -31,20 +57,438 @@ fn main() {
     opts.optflag("n", "nonffi",
                  "print `unsafe`s that include non-FFI unsafe behaviours");
     opts.optflag("f", "ffi", "print `unsafe`s that do FFI calls");
+    opts.optflag("", "nonffi-only", "only print `unsafe`s with non-FFI unsafe behaviours");
+    opts.optflag("", "ffi-only", "only print `unsafe`s that do FFI calls");
+    opts.optflag("v", "verbose", "print extra details about each unsafe action");
+    opts.optflag("e", "extern-items",
+                 "print the functions and statics declared in `extern` blocks");
+    opts.optflag("x", "exports",
+                 "print functions callable from foreign code, i.e. `#[no_mangle]` \
+                  or with a non-Rust ABI, and `#[no_mangle]` statics");
     opts.optmulti("L", "library-path",
                   "directories to add to crate search path", "DIR");
+    opts.optflag("", "attrs",
+                 "print items with attributes that affect soundness, like \
+                  `#[link_section]`, `#[export_name]` and `#[no_mangle]` statics");
+    opts.optflag("u", "unused",
+                 "print `unsafe` blocks and functions that don't contain any \
+                  unsafe actions, and `unsafe` blocks nested inside others");
+    opts.optflag("", "unsafe-traits",
+                 "print the unsafe methods declared by traits, and the \
+                  implementations of them");
+    opts.optflag("", "target-features",
+                 "print functions declared with `#[target_feature]`, which \
+                  are unsafe to call on CPUs without those features");
+    opts.optflag("", "macros",
+                 "print `unsafe` written in exported `macro_rules!` definitions, \
+                  which only appears in the crates that use them");
+    opts.optflag("", "static-muts",
+                 "print `static mut`s whose type has a destructor or \
+                  references, or whose address is passed to foreign code");
+    opts.optflag("", "thin-wrappers",
+                 "print public functions that just forward their arguments to an \
+                  unsafe function, without checking anything");
+    opts.optflag("", "extended",
+                 "also count calls in unsafe code to safe functions that are \
+                  dangerous there, like `mem::forget` and `Box::into_raw`");
+    opts.optflag("", "taint-exec",
+                 "print everything that runs another program, like \
+                  `Command::new` and `libc::system`");
+    opts.optflag("", "fuzz-targets",
+                 "suggest fuzz targets: public functions containing unsafe code \
+                  that only take primitives, `&str` or `&[u8]`");
+    opts.optflag("", "miri",
+                 "list the tests to run under Miri for each function containing \
+                  unsafe code, or a `#[test]` stub if there are none");
+    opts.optmulti("", "calls-into",
+                  "only print `unsafe`s that call unsafe functions from CRATE",
+                  "CRATE");
+    opts.optopt("", "density",
+                "print functions with more than DENSITY unsafe actions per line",
+                "DENSITY");
+    opts.optopt("", "max-lines",
+                "flag unsafe blocks and functions spanning more than N lines", "N");
+    opts.optopt("", "min",
+                "only print `unsafe`s containing at least N unsafe actions", "N");
+    opts.optflag("", "stats",
+                 "print a table of the number of unsafe actions in each category, \
+                  instead of listing them");
+    opts.optflag("", "by-file",
+                 "print the number of unsafe blocks and actions in each file, \
+                  most actions first, instead of listing them");
+    opts.optflag("", "tree",
+                 "print the number of unsafe blocks and actions in each module, \
+                  as a tree, instead of listing them");
+    opts.optopt("", "top",
+                "print the N unsafe blocks and functions with the most unsafe actions, \
+                 with their item paths, instead of listing them all", "N");
+    opts.optopt("", "max-results",
+                "only print the N `unsafe`s with the most unsafe actions", "N");
+    opts.optopt("", "coverage",
+                "mark unsafe code that isn't executed according to the LCOV \
+                 tracefile FILE", "FILE");
+    opts.optmulti("", "root",
+                  "also analyse every package under DIR, resolving their \
+                   configuration within DIR", "DIR");
+    opts.optflag("", "stdin-paths",
+                 "also read crate roots to analyse from stdin, one per line");
+    opts.optflag("", "lint-anomalies",
+                 "highlight unsafe code in crates whose manifest sets \
+                  `unsafe_code` to `forbid` or `deny`");
+    opts.optflag("", "age",
+                 "print how many days ago each unsafe block was last changed, \
+                  according to `git blame`");
+    opts.optopt("", "older-than",
+                "only print `unsafe`s last changed more than DAYS days ago", "DAYS");
+    opts.optopt("", "newer-than",
+                "only print `unsafe`s last changed less than DAYS days ago", "DAYS");
+    opts.optopt("", "since",
+                "only print `unsafe`s with lines added or changed since the git \
+                 revision REV (`--forbid-category` still checks the whole crate)",
+                "REV");
+    opts.optflag("", "hotspots",
+                 "print each file's number of commits and unsafe actions, \
+                  flagging those with more of both than average");
+    opts.optopt("", "profile",
+                "turn on a preset selection of flags: `minimal` (just -n), \
+                 `default` (-n -f) or `strict` (everything that reports \
+                 possible problems)", "PROFILE");
+    opts.optopt("", "audited",
+                "don't report calls to the unsafe functions listed in FILE, one \
+                 fully-qualified path (e.g. `mycrate::raw::checked_copy`) per line",
+                "FILE");
+    opts.optopt("", "detectors",
+                "read extra categories from FILE, with lines like \
+                 `mycrate::raw::poke = poke`: calls to that function in \
+                 unsafe code are counted as `poke`", "FILE");
+    opts.optopt("", "progress",
+                "report progress on stderr; the only FORMAT is `json`, \
+                 giving one event object per line", "FORMAT");
+    opts.optopt("", "format",
+                "output format: `human` (default), `snapshot`, a canonical \
+                 form suitable for committing as a golden file, `json`, \
+                 an index keyed by rustdoc item paths, `sarif`, `bundle`, \
+                 a compressed file of everything found, written to the \
+                 `-o` file, or `heatmap`, a CSV of the number of unsafe \
+                 actions on each line", "FORMAT");
+    opts.optopt("o", "output", "where to write `--format bundle` output", "FILE");
+    opts.optopt("", "only",
+                "only print unsafe actions in the comma-separated CATEGORIES, \
+                 e.g. `transmute,raw_deref`, instead of those picked by -n/-f",
+                "CATEGORIES");
+    opts.optopt("", "exclude",
+                "don't print unsafe actions in the comma-separated CATEGORIES, \
+                 e.g. `ffi,asm`", "CATEGORIES");
+    opts.optmulti("", "include-path",
+                  "only print findings in files matching one of the \
+                   comma-separated GLOBS, e.g. `src/**`", "GLOBS");
+    opts.optmulti("", "exclude-path",
+                  "don't print findings in files matching one of the \
+                   comma-separated GLOBS, e.g. `tests/**,benches/**`", "GLOBS");
+    opts.optflag("", "no-tests",
+                 "don't print `unsafe`s in `#[test]` functions or `#[cfg(test)]` \
+                  items");
+    opts.optopt("", "macro-spans",
+                "where to report unsafe code from macro expansions: \
+                 `definition` (default, wherever the expansion's spans point), \
+                 `call-site`, or `hide-external` to skip code from other \
+                 crates' macros", "MODE");
+    opts.optmulti("", "module",
+                  "only print `unsafe`s in items under the module PATH, e.g. \
+                   `mycrate::net::raw`", "PATH");
+    opts.optflag("", "fns-only", "only print `unsafe fn`s, not `unsafe` blocks");
+    opts.optflag("", "blocks-only", "only print `unsafe` blocks, not `unsafe fn`s");
+    opts.optflag("", "include-generated",
+                 "also print compiler-generated `unsafe` blocks (e.g. from desugarings)");
+    opts.optflag("", "show-suppressed",
+                 "also print `unsafe`s marked with `// unsafe_ls: ignore` or \
+                  `#[cfg_attr(unsafe_ls, allow(unsafe_ls))]`");
+    opts.optopt("", "suppressions",
+                "don't print `unsafe`s whose fingerprints are listed in FILE (as written \
+                 by `--write-suppressions`)", "FILE");
+    opts.optopt("", "write-suppressions",
+                "write the fingerprints of everything found to FILE, for `--suppressions`",
+                "FILE");
+    opts.optopt("", "in-fn",
+                "only print `unsafe`s in items whose name matches REGEX", "REGEX");
+    opts.optmulti("", "crate",
+                  "only print findings in the crate called NAME, when analysing several",
+                  "NAME");
+    opts.optflag("", "pub-only",
+                 "only print `unsafe`s in items that are visible outside \
+                  the crate");
+    opts.optopt("", "forbid-category",
+                "fail if there are any unsafe actions in the comma-separated \
+                 CATEGORIES anywhere in the crate, e.g. `asm,static_mut`",
+                "CATEGORIES");
+    opts.optmulti("", "deny",
+                  "the same as `--forbid-category`: fail if there are any \
+                   unsafe actions in the comma-separated CATEGORIES, whatever \
+                   is printed", "CATEGORIES");
+    opts.optopt("", "features-a",
+                "with `--features-b`, print the `unsafe`s that only exist when \
+                 compiling with one of the two comma-separated sets of FEATURES",
+                "FEATURES");
+    opts.optopt("", "features-b", "see `--features-a`", "FEATURES");
+    opts.optflag("", "collapse",
+                 "merge identical findings on the same line (e.g. from a macro) \
+                  into one, with a count");
+    opts.optflag("", "no-snippets", "don't include source code in SARIF or bundle output");
+    opts.optopt("", "check-snapshot",
+                "compare the snapshot output against FILE, failing if they differ",
+                "FILE");
 
     let name = args.next().unwrap();
     let matches = opts.parse(args).unwrap();
     if matches.opt_present("help") {
         println!("{}",
                  opts.usage(&format!("{} - find all unsafe blocks and print the \
-                                      unsafe actions within them", name)));
+                                      unsafe actions within them\n\n\
+                                      Usage: {0} [options] CRATE...\n       \
+                                      {0} scaffold-docs [options] CRATE...",
+                                     name)));
         return;
     }
 
-    let nonffi = matches.opt_present("nonffi");
-    let ffi = matches.opt_present("ffi");
+    // `unsafe_ls scaffold-docs crate.rs ...`
+    let scaffold_docs = matches.free.first().map_or(false, |s| s == "scaffold-docs");
+    let mut crates = if scaffold_docs {
+        matches.free[1..].to_vec()
+    } else {
+        matches.free.clone()
+    };
+
+    let profile = match matches.opt_str("profile") {
+        None => NO_PROFILE,
+        Some(ref p) if p == "minimal" => MINIMAL_PROFILE,
+        Some(ref p) if p == "default" => DEFAULT_PROFILE,
+        Some(ref p) if p == "strict" => STRICT_PROFILE,
+        Some(p) => {
+            let _ = writeln!(&mut io::stderr(), "{}: unknown profile `{}`", name, p);
+            process::exit(2)
+        }
+    };
+    // flags can be turned on by the profile or on the command line.
+    let flag = |f: &str| matches.opt_present(f) || profile.contains(&f);
+
+    let (nonffi_only, ffi_only) = (matches.opt_present("nonffi-only"),
+                                   matches.opt_present("ffi-only"));
+    if nonffi_only && ffi_only {
+        let _ = writeln!(&mut io::stderr(), "{}: `--nonffi-only` and `--ffi-only` can't \
+                                             be used together", name);
+        process::exit(2)
+    }
+    // with nothing specified, report everything (i.e. `-n -f`).
+    let (nonffi, ffi) = match (flag("nonffi"), flag("ffi")) {
+        _ if nonffi_only || ffi_only => (nonffi_only, ffi_only),
+        (false, false) => (true, true),
+        x => x,
+    };
+    let verbose = flag("verbose");
+    let extern_items = flag("extern-items");
+    let exports = flag("exports");
+    let unused = flag("unused");
+    let thin_wrappers = flag("thin-wrappers");
+    let unsafe_traits = flag("unsafe-traits");
+    let target_features = flag("target-features");
+    let macros = flag("macros");
+    let static_muts = flag("static-muts");
+    let attrs = flag("attrs");
+    let fuzz_targets = matches.opt_present("fuzz-targets");
+    let taint_exec = matches.opt_present("taint-exec");
+    let extended = flag("extended");
+    let miri = matches.opt_present("miri");
+    let calls_into = matches.opt_strs("calls-into");
+    let lint_anomalies = flag("lint-anomalies");
+    let density = parse_opt::<f64>(&matches, &name, "density", "density");
+    let min_actions = parse_opt::<usize>(&matches, &name, "min", "minimum").unwrap_or(1);
+    let coverage = matches.opt_str("coverage").map(|path| {
+        coverage::Coverage::from_lcov(Path::new(&path)).unwrap_or_else(|e| {
+            let _ = writeln!(&mut io::stderr(), "{}: couldn't read `{}`: {}", name, path, e);
+            process::exit(2)
+        })
+    });
+    let days = |opt: &str| parse_opt::<i64>(&matches, &name, opt, "number of days");
+    let older_than = days("older-than");
+    let newer_than = days("newer-than");
+    let age = matches.opt_present("age") || older_than.is_some() || newer_than.is_some();
+    let since = matches.opt_str("since");
+    if let Some(ref rev) = since {
+        if !git::is_revision(rev) {
+            let _ = writeln!(&mut io::stderr(), "{}: unknown git revision `{}`", name, rev);
+            process::exit(2)
+        }
+    }
+    let hotspots = matches.opt_present("hotspots");
+    let audited = matches.opt_str("audited").map(|path| {
+        read_list(Path::new(&path)).unwrap_or_else(|e| {
+            let _ = writeln!(&mut io::stderr(), "{}: couldn't read `{}`: {}", name, path, e);
+            process::exit(2)
+        })
+    }).unwrap_or(HashSet::new());
+    let detectors = matches.opt_str("detectors").map(|path| {
+        read_detectors(Path::new(&path)).unwrap_or_else(|e| {
+            let _ = writeln!(&mut io::stderr(), "{}: couldn't read `{}`: {}", name, path, e);
+            process::exit(2)
+        })
+    }).unwrap_or(HashMap::new());
+    let progress = match matches.opt_str("progress") {
+        None => false,
+        Some(ref s) if s == "json" => true,
+        Some(s) => {
+            let _ = writeln!(&mut io::stderr(), "{}: unknown progress format `{}`", name, s);
+            process::exit(2)
+        }
+    };
+    let categories = |opt: &str| matches.opt_strs(opt).iter()
+        .flat_map(|s| s.split(','))
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>();
+    let only = categories("only");
+    let exclude = categories("exclude");
+    let no_tests = matches.opt_present("no-tests");
+    let pub_only = matches.opt_present("pub-only");
+    let include_generated = matches.opt_present("include-generated");
+    let show_suppressed = matches.opt_present("show-suppressed");
+    let suppressions = matches.opt_str("suppressions").map(|path| {
+        read_list(Path::new(&path)).unwrap_or_else(|e| {
+            let _ = writeln!(&mut io::stderr(), "{}: couldn't read `{}`: {}", name, path, e);
+            process::exit(2)
+        })
+    }).unwrap_or(HashSet::new());
+    let write_suppressions = matches.opt_str("write-suppressions");
+    let stats = matches.opt_present("stats");
+    let max_lines = parse_opt::<usize>(&matches, &name, "max-lines", "number of lines");
+    let by_file = matches.opt_present("by-file");
+    let tree = matches.opt_present("tree");
+    let top = parse_opt::<usize>(&matches, &name, "top", "number");
+    let max_results = parse_opt::<usize>(&matches, &name, "max-results",
+                                         "number of results");
+    let modules = matches.opt_strs("module");
+    let crate_names = matches.opt_strs("crate");
+    let in_fn = matches.opt_str("in-fn").map(|s| {
+        Regex::new(&s).unwrap_or_else(|e| {
+            let _ = writeln!(&mut io::stderr(), "{}: invalid regex `{}`: {}", name, s, e);
+            process::exit(2)
+        })
+    });
+    let (fns_only, blocks_only) = (matches.opt_present("fns-only"),
+                                   matches.opt_present("blocks-only"));
+    if fns_only && blocks_only {
+        let _ = writeln!(&mut io::stderr(), "{}: `--fns-only` and `--blocks-only` can't \
+                                             be used together", name);
+        process::exit(2)
+    }
+    let macro_spans = match matches.opt_str("macro-spans") {
+        None => MacroSpans::Definition,
+        Some(ref s) if s == "definition" => MacroSpans::Definition,
+        Some(ref s) if s == "call-site" => MacroSpans::CallSite,
+        Some(ref s) if s == "hide-external" => MacroSpans::HideExternal,
+        Some(s) => {
+            let _ = writeln!(&mut io::stderr(), "{}: unknown macro span mode `{}`", name, s);
+            process::exit(2)
+        }
+    };
+    let include_paths = categories("include-path");
+    let exclude_paths = categories("exclude-path");
+    let mut forbid_categories = categories("forbid-category");
+    forbid_categories.extend(categories("deny").into_iter());
+    // a typo would otherwise silently match nothing, and so pass
+    // `--forbid-category`.
+    let known = visitor::categories().into_iter().map(|c| c.0)
+        .chain(detectors.values().cloned())
+        .collect::<HashSet<_>>();
+    for c in only.iter().chain(exclude.iter()).chain(forbid_categories.iter()) {
+        if !known.contains(c) {
+            let _ = writeln!(&mut io::stderr(), "{}: unknown category `{}`", name, c);
+            process::exit(2)
+        }
+    }
+    let collapse = matches.opt_present("collapse");
+    let output_file = matches.opt_str("output");
+    let snippets = !matches.opt_present("no-snippets");
+    let check_snapshot = matches.opt_str("check-snapshot");
+    let format = match matches.opt_str("format") {
+        None => if check_snapshot.is_some() { Format::Snapshot } else { Format::Human },
+        Some(ref s) if s == "human" => Format::Human,
+        Some(ref s) if s == "snapshot" => Format::Snapshot,
+        Some(ref s) if s == "json" => Format::Json,
+        Some(ref s) if s == "sarif" => Format::Sarif,
+        Some(ref s) if s == "bundle" => Format::Bundle,
+        Some(ref s) if s == "heatmap" => Format::Heatmap,
+        Some(s) => {
+            let _ = writeln!(&mut io::stderr(), "{}: unknown format `{}`", name, s);
+            process::exit(2)
+        }
+    };
+    // these replace the listing with their own plain text.
+    let summaries = [("--stats", stats), ("--by-file", by_file), ("--tree", tree),
+                     ("--top", top.is_some())];
+    for &(flag, on) in &summaries {
+        if on && format != Format::Human && format != Format::Snapshot {
+            let _ = writeln!(&mut io::stderr(), "{}: `{}` needs `--format human` or \
+                                                 `snapshot`", name, flag);
+            process::exit(2)
+        }
+    }
+    // fingerprints are only collected while listing.
+    if write_suppressions.is_some() &&
+        (format != Format::Human && format != Format::Snapshot ||
+         summaries.iter().any(|s| s.1)) {
+        let _ = writeln!(&mut io::stderr(), "{}: `--write-suppressions` needs `--format human` \
+                                             or `snapshot`, and a listing", name);
+        process::exit(2)
+    }
+    // the scaffolding is printed as plain text instead of the listing.
+    if scaffold_docs {
+        if format != Format::Human && format != Format::Snapshot {
+            let _ = writeln!(&mut io::stderr(), "{}: `scaffold-docs` needs `--format human` \
+                                                 or `snapshot`", name);
+            process::exit(2)
+        }
+        for &(flag, on) in &summaries {
+            if on {
+                let _ = writeln!(&mut io::stderr(), "{}: `scaffold-docs` can't be used with \
+                                                     `{}`", name, flag);
+                process::exit(2)
+            }
+        }
+    }
+    if format == Format::Bundle && output_file.is_none() {
+        let _ = writeln!(&mut io::stderr(), "{}: `--format bundle` needs `-o FILE`", name);
+        process::exit(2)
+    }
+    let features = |opt: &str| matches.opt_str(opt).map(|s| {
+        s.split(',').map(|f| f.trim().to_string()).filter(|f| !f.is_empty())
+            .collect::<Vec<_>>()
+    });
+    let compare_features = match (features("features-a"), features("features-b")) {
+        (None, None) => None,
+        (Some(a), Some(b)) => {
+            if format != Format::Human && format != Format::Snapshot {
+                let _ = writeln!(&mut io::stderr(), "{}: comparing features needs \
+                                                     `--format human` or `snapshot`", name);
+                process::exit(2)
+            }
+            if write_suppressions.is_some() {
+                let _ = writeln!(&mut io::stderr(), "{}: `--write-suppressions` can't be used \
+                                                     when comparing features", name);
+                process::exit(2)
+            }
+            if scaffold_docs {
+                let _ = writeln!(&mut io::stderr(), "{}: `scaffold-docs` can't be used when \
+                                                     comparing features", name);
+                process::exit(2)
+            }
+            Some((a, b))
+        }
+        _ => {
+            let _ = writeln!(&mut io::stderr(), "{}: `--features-a` and `--features-b` \
+                                                 must be used together", name);
+            process::exit(2)
+        }
+    };
     let mut search_paths = SearchPaths::new();
     for path in matches.opt_strs("L").into_iter() {
         search_paths.add_path(&*path)
@@ -52,81 +496,526 @@ fn main() {
     search_paths.add_path(DEFAULT_LIB_DIR);
     let externs = HashMap::new();
 
+    let roots = matches.opt_strs("root").iter().map(PathBuf::from).collect::<Vec<_>>();
+    for root in &roots {
+        for path in roots::crate_roots(root) {
+            crates.push(path.to_string_lossy().into_owned())
+        }
+    }
+
     let session = Arc::new(Session {
         nonffi: nonffi,
         ffi: ffi,
+        scaffold_docs: scaffold_docs,
+        verbose: verbose,
+        extern_items: extern_items,
+        exports: exports,
+        unused: unused,
+        thin_wrappers: thin_wrappers,
+        unsafe_traits: unsafe_traits,
+        target_features: target_features,
+        macros: macros,
+        static_muts: static_muts,
+        attrs: attrs,
+        fuzz_targets: fuzz_targets,
+        taint_exec: taint_exec,
+        extended: extended,
+        miri: miri,
+        calls_into: calls_into,
+        lint_anomalies: lint_anomalies,
+        density: density,
+        min_actions: cmp::max(min_actions, 1),
+        max_results: max_results,
+        stats: stats,
+        max_lines: max_lines,
+        by_file: by_file,
+        tree: tree,
+        top: top,
+        age: age,
+        hotspots: hotspots,
+        older_than: older_than,
+        since: since,
+        newer_than: newer_than,
+        coverage: coverage,
+        detectors: detectors,
+        audited: audited,
+        only: only,
+        exclude: exclude,
+        no_tests: no_tests,
+        pub_only: pub_only,
+        include_generated: include_generated,
+        show_suppressed: show_suppressed,
+        suppressions: suppressions,
+        write_suppressions: write_suppressions.is_some(),
+        modules: modules,
+        crate_names: crate_names,
+        in_fn: in_fn,
+        fns_only: fns_only,
+        blocks_only: blocks_only,
+        macro_spans: macro_spans,
+        include_paths: include_paths,
+        exclude_paths: exclude_paths,
+        forbid_categories: forbid_categories,
+        compare_features: compare_features,
+        progress: progress,
+        collapse: collapse,
+        snippets: snippets,
+        format: format,
+        roots: roots.clone(),
         externs: externs,
         search_paths: search_paths,
     });
 
-    for name in matches.free.iter() {
+    if matches.opt_present("stdin-paths") {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            let line = line.unwrap();
+            let line = line.trim();
+            if !line.is_empty() {
+                crates.push(line.to_string())
+            }
+        }
+    }
+
+    let mut output = String::new();
+    if session.format == Format::Heatmap {
+        println!("file,line,actions");
+    }
+    let mut failed = false;
+    // the `--format json`, `sarif` or `bundle` output for each crate,
+    // combined into one document at the end.
+    let mut documents = Vec::new();
+    let mut fingerprints = Vec::new();
+    for (i, name) in crates.iter().enumerate() {
+        if session.progress {
+            progress("crate_started", vec![("crate", name.to_json()),
+                                           ("index", i.to_json()),
+                                           ("total", crates.len().to_json())]);
+        }
         let sess = session.clone();
-        let name = Path::new(name).to_owned();
+        let path = Path::new(name).to_owned();
         // the compiler has all sorts of thread locals.
-        thread::spawn(move || {
-            sess.run_library(name);
+        let (out, violations, found) = thread::spawn(move || {
+            sess.run_library(path)
         }).join().unwrap();
+        fingerprints.extend(found.into_iter());
+        if !violations.is_empty() {
+            let mut stderr = io::stderr();
+            for line in &violations {
+                let _ = writeln!(&mut stderr, "{}", line);
+            }
+            failed = true;
+        }
+        if session.progress {
+            progress("crate_finished", vec![("crate", name.to_json())]);
+        }
+
+        if session.format == Format::Json || session.format == Format::Sarif ||
+            session.format == Format::Bundle {
+            // nothing at all for a crate skipped by `--crate`.
+            if !out.is_empty() {
+                documents.push(Json::from_str(&out).unwrap())
+            }
+        } else if check_snapshot.is_some() {
+            output.push_str(&out)
+        } else {
+            // each crate's output is written in one go, so that it can
+            // never be interleaved with another's, even if they end
+            // up being analysed concurrently.
+            let stdout = io::stdout();
+            let mut stdout = stdout.lock();
+            stdout.write_all(out.as_bytes()).unwrap();
+            stdout.flush().unwrap();
+        }
+    }
+
+    let document = match (session.format, output_file) {
+        (Format::Json, _) => Some(Json::Array(documents)),
+        (Format::Sarif, _) => Some(sarif::log(documents)),
+        (Format::Bundle, Some(path)) => {
+            let mut rules = visitor::categories();
+            for category in session.detectors.values() {
+                if !rules.iter().any(|r| r.0 == *category) {
+                    rules.push((category.clone(), category.clone()))
+                }
+            }
+            if let Err(e) = bundle::write(Path::new(&path), &rules, documents) {
+                let _ = writeln!(&mut io::stderr(), "{}: couldn't write `{}`: {}",
+                                 name, path, e);
+                process::exit(2)
+            }
+            None
+        }
+        _ => None,
+    };
+    if let Some(document) = document {
+        let document = format!("{}\n", json::as_pretty_json(&document));
+        if check_snapshot.is_some() {
+            output.push_str(&document)
+        } else {
+            print!("{}", document)
+        }
+    }
+
+    if let Some(path) = write_suppressions {
+        let mut contents = String::from("# fingerprints of audited unsafes, see \
+                                         `unsafe_ls --suppressions`\n");
+        for line in &fingerprints {
+            contents.push_str(line);
+            contents.push('\n');
+        }
+        if let Err(e) = File::create(&path).and_then(|mut f| f.write_all(contents.as_bytes())) {
+            let _ = writeln!(&mut io::stderr(), "{}: couldn't write `{}`: {}", name, path, e);
+            process::exit(2)
+        }
+    }
+
+    if let Some(snapshot) = check_snapshot {
+        let mut expected = String::new();
+        if let Err(e) = File::open(&snapshot).and_then(|mut f| f.read_to_string(&mut expected)) {
+            let _ = writeln!(&mut io::stderr(), "{}: couldn't read `{}`: {}",
+                             name, snapshot, e);
+            process::exit(2)
+        }
+        if expected != output {
+            let mut stderr = io::stderr();
+            let _ = writeln!(&mut stderr, "{}: output differs from `{}`", name, snapshot);
+            for line in report::snapshot_diff(&expected, &output) {
+                let _ = writeln!(&mut stderr, "{}", line);
+            }
+            process::exit(1)
+        }
+    }
+
+    if failed {
+        process::exit(1)
     }
 }
 
+/// How to attribute unsafe code produced by macro expansions.
+#[derive(Clone, Copy, PartialEq)]
+enum MacroSpans {
+    Definition,
+    CallSite,
+    HideExternal,
+}
 
 struct Session {
     nonffi: bool,
     ffi: bool,
+    scaffold_docs: bool,
+    verbose: bool,
+    extern_items: bool,
+    exports: bool,
+    unused: bool,
+    thin_wrappers: bool,
+    unsafe_traits: bool,
+    target_features: bool,
+    macros: bool,
+    static_muts: bool,
+    attrs: bool,
+    fuzz_targets: bool,
+    taint_exec: bool,
+    extended: bool,
+    miri: bool,
+    calls_into: Vec<String>,
+    lint_anomalies: bool,
+    density: Option<f64>,
+    /// `--min`: the fewest unsafe actions an `unsafe` needs to be
+    /// printed.
+    min_actions: usize,
+    max_results: Option<usize>,
+    stats: bool,
+    max_lines: Option<usize>,
+    by_file: bool,
+    tree: bool,
+    top: Option<usize>,
+    age: bool,
+    hotspots: bool,
+    older_than: Option<i64>,
+    since: Option<String>,
+    newer_than: Option<i64>,
+    coverage: Option<coverage::Coverage>,
+    detectors: HashMap<String, String>,
+    audited: HashSet<String>,
+    only: Vec<String>,
+    exclude: Vec<String>,
+    no_tests: bool,
+    pub_only: bool,
+    include_generated: bool,
+    show_suppressed: bool,
+    suppressions: HashSet<String>,
+    write_suppressions: bool,
+    modules: Vec<String>,
+    crate_names: Vec<String>,
+    in_fn: Option<Regex>,
+    fns_only: bool,
+    blocks_only: bool,
+    macro_spans: MacroSpans,
+    include_paths: Vec<String>,
+    exclude_paths: Vec<String>,
+    forbid_categories: Vec<String>,
+    compare_features: Option<(Vec<String>, Vec<String>)>,
+    progress: bool,
+    collapse: bool,
+    snippets: bool,
+    format: Format,
+    /// The `--root` directories.
+    roots: Vec<PathBuf>,
     externs: Externs,
     search_paths: SearchPaths,
 }
 
+
 impl Session {
-    fn run_library(&self, path: PathBuf) {
-        get_ast(path, self.search_paths.clone(), self.externs.clone(), |tcx| {
+    /// Returns the output for the crate at `path`, along with a
+    /// message for each unsafe action in a `--forbid-category`, and
+    /// the lines for `--write-suppressions`.
+    fn run_library(&self, path: PathBuf) -> (String, Vec<String>, Vec<String>) {
+        let lint = lints::unsafe_code_level(&path, roots::root_of(&path, &self.roots));
+        // unsafe code that got past one of these came from a macro
+        // (or `#[allow]`), which deserves a closer look.
+        let forbidden = lint.as_ref().map_or(false, |&(ref l, _)| l == "forbid" || l == "deny");
+
+        if let Some((ref a, ref b)) = self.compare_features {
+            return (self.compare_features(path, a, b), Vec::new(), Vec::new())
+        }
+
+        let (search_paths, externs) = (self.search_paths.clone(), self.externs.clone());
+        get_ast(path, Vec::new(), self.miri, search_paths, externs,
+                |tcx, exported, crate_name| {
             let cm = tcx.sess.codemap();
 
-            let mut visitor = visitor::UnsafeVisitor::new(tcx);
+            let mut visitor = visitor::UnsafeVisitor::new(tcx, crate_name);
+            visitor.detectors = self.detectors.clone();
+            visitor.audited = self.audited.clone();
+            visitor.extended = self.extended;
             visitor.check_crate(tcx.map.krate());
 
-            for (_, info) in visitor.unsafes.iter() {
-                // compiler generated block, so we don't care.
-                if info.compiler { continue }
+            if self.progress {
+                let findings = visitor.unsafes.values()
+                    .filter(|i| !i.compiler)
+                    .fold(0, |a, i| a + i.kinds().iter().fold(0, |a, k| a + k.2.len()));
+                progress("analysis_complete", vec![("crate", crate_name.to_json()),
+                                                   ("unsafes", visitor.unsafes.len().to_json()),
+                                                   ("findings", findings.to_json())]);
+            }
 
-                let n = info.raw_deref.len()
-                    + info.static_mut.len()
-                    + info.unsafe_call.len()
-                    + info.asm.len()
-                    + info.transmute.len()
-                    + info.transmute_imm_to_mut.len()
-                    + info.cast_raw_ptr_const_to_mut.len();
+            // checked across the whole crate, regardless of what's
+            // being printed or suppressed.
+            let mut violations = Vec::new();
+            for info in visitor.unsafes.values().filter(|i| !i.compiler) {
+                for &(name, _, spans) in &info.kinds() {
+                    if !self.forbid_categories.iter().any(|c| c == name) { continue }
+                    for &span in spans {
+                        violations.push(format!("{}: forbidden {} in `{}`",
+                                               cm.span_to_string(span), name,
+                                               info.item_path));
+                    }
+                }
+            }
 
-                let f = info.ffi.len();
+            // `--crate` only picks what's printed.
+            if !self.crate_names.is_empty() && !self.crate_names.iter().any(|c| c == crate_name) {
+                return (String::new(), violations, Vec::new())
+            }
+
+            // the same selection, whatever's being printed.
+            let mut changed = HashMap::new();
+            let unsafes = visitor.unsafes.values().filter(|info| {
+                self.keep(cm, crate_name, exported, info, &mut changed)
+            }).collect::<Vec<_>>();
+            // everything but the listing just leaves out the suppressed
+            // ones, while the listing counts them.
+            let shown = unsafes.iter().cloned().filter(|info| {
+                self.show_suppressed || !self.suppressed(cm, info)
+            }).collect::<Vec<_>>();
+
+            if self.stats {
+                return (self.stats(crate_name, &shown), violations, Vec::new())
+            }
 
-                if (self.nonffi && n > 0) || (self.ffi && f > 0) {
-                    use syntax::codemap::Pos;
+            if self.by_file {
+                return (self.by_file(cm, &visitor, &shown), violations, Vec::new())
+            }
+
+            if self.tree {
+                return (self.tree(crate_name, &shown), violations, Vec::new())
+            }
 
+            if let Some(n) = self.top {
+                return (self.top(cm, crate_name, &shown, n), violations, Vec::new())
+            }
+
+            if self.scaffold_docs {
+                return (scaffold::scaffold_docs(cm, &visitor), violations, Vec::new())
+            }
+
+            if self.format == Format::Json {
+                let mut index = item_index(crate_name, &shown);
+                if let (&mut Json::Object(ref mut o), Some(&(ref level, _))) =
+                    (&mut index, lint.as_ref()) {
+                    o.insert("unsafe_code_lint".to_string(), level.to_json());
+                }
+                return (index.to_string(), violations, Vec::new());
+            }
+
+            if self.format == Format::Heatmap {
+                return (heatmap(cm, &shown), violations, Vec::new());
+            }
+
+            if self.format == Format::Bundle {
+                let bundle = bundle::crate_bundle(cm, crate_name, &shown, self.snippets);
+                return (bundle.to_string(), violations, Vec::new());
+            }
+
+            if self.format == Format::Sarif {
+                let unsafes = shown.into_iter().filter(|info| {
+                    info.kinds().iter().any(|k| self.wanted(k.0) && !k.2.is_empty())
+                }).collect::<Vec<_>>();
+                let run = sarif::run(cm, &unsafes, self.snippets);
+                return (run.to_string(), violations, Vec::new());
+            }
+
+            let mut entries = Vec::new();
+            let entry = |span: Span, summary: String| {
+                let span = if self.macro_spans == MacroSpans::CallSite {
+                    macros::call_site(cm, span)
+                } else {
+                    span
+                };
+                let lo = cm.lookup_char_pos_adj(span.lo);
+                Entry {
+                    file: lo.filename,
+                    line: lo.line,
+                    col: lo.col.to_usize() + 1,
+                    summary: summary,
+                    lines: Vec::new(),
+                    untested: false,
+                }
+            };
+
+            // for the summary at the end.
+            let (mut reported, mut unused, mut nested, mut suppressed) = (0, 0, 0, 0);
+            let mut oversized = 0;
+            let mut fingerprints = Vec::new();
+            // `(actions, entry)`, when only the biggest are printed.
+            let mut biggest = Vec::new();
+
+            for &info in &unsafes {
+                let is_suppressed = self.suppressed(cm, info);
+                if self.write_suppressions {
+                    fingerprints.push(format!("{} # {}: `{}`", info.fingerprint(cm),
+                                              cm.span_to_string(info.span),
+                                              info.item_path));
+                }
+                if is_suppressed && !self.show_suppressed {
+                    suppressed += 1;
+                    continue
+                }
+
+                // not just anything reported: casts, `UnsafeCell::get`,
+                // etc. are safe.
+                let needed = !info.needs_unsafe.is_empty();
+
+                if !needed && !info.compiler { unused += 1 }
+                nested += info.nested.len();
+
+                if self.unused && !needed && !info.compiler {
+                    entries.push(entry(info.span, if info.is_fn {
+                        // the unsafety is purely a contract on callers
+                        // (which should be documented), or vestigial.
+                        format!("unsafe fn `{}` without unsafe actions", info.item_path)
+                    } else {
+                        "unnecessary unsafe block".to_string()
+                    }));
+                }
+                if self.unused {
+                    for &span in &info.nested {
+                        entries.push(entry(span, format!("nested unsafe block inside unsafe {}",
+                                                         if info.is_fn {"fn"} else {"block"})));
+                    }
+                }
+
+                let wanted = self.wanted_actions(info);
+
+                if wanted >= self.min_actions {
                     let mut v = Vec::new();
-                    if self.nonffi {
-                        for vv in [&info.raw_deref, &info.static_mut,
-                                   &info.unsafe_call, &info.asm,
-                                   &info.transmute,
-                                   &info.transmute_imm_to_mut,
-                                   &info.cast_raw_ptr_const_to_mut].iter() {
-                            v.extend(vv.iter().cloned())
+                    for &(name, _, spans) in &info.kinds() {
+                        if self.wanted(name) {
+                            v.extend(spans.iter().cloned())
                         }
                     }
-                    if self.ffi {
-                        v.extend(info.ffi.iter().cloned())
-                    }
+                    v.extend(info.cfg_miri.iter().cloned());
 
-                    let lo = cm.lookup_char_pos_adj(info.span.lo);
-
-                    // print the summary line
-                    println!("{}:{}:{}: {} with {:?}",
-                             lo.filename,
-                             lo.line, lo.col.to_usize() + 1,
-                             if info.is_fn {"fn"} else {"block"},
-                             *info);
+                    let mut e = entry(info.span,
+                                      format!("{}{}{}{}{} with {:?}",
+                                              if info.compiler {"compiler generated "}
+                                              else {""},
+                                              if info.is_fn {"fn"} else {"block"},
+                                              if info.in_const {
+                                                  format!(" in const initializer of `{}`",
+                                                          info.item_path)
+                                              } else {
+                                                  String::new()
+                                              },
+                                              if info.in_drop {" in Drop::drop"} else {""},
+                                              match info.external_trait {
+                                                  Some((ref path, ref krate)) =>
+                                                      format!(" in impl of `{}` from {}",
+                                                              path, krate),
+                                                  None => String::new(),
+                                              },
+                                              *info));
+                    if self.age {
+                        let now = time::get_time().sec;
+                        let days = git::line_time(&e.file, e.line)
+                            .map(|t| (now - t) / (24 * 60 * 60));
+                        let wanted = match days {
+                            Some(d) => self.older_than.map_or(true, |o| d > o) &&
+                                self.newer_than.map_or(true, |n| d < n),
+                            None => self.older_than.is_none() && self.newer_than.is_none(),
+                        };
+                        if !wanted { continue }
+                        if let Some(d) = days {
+                            e.summary.push_str(&format!(" ({} days old)", d));
+                        }
+                    }
+                    if self.verbose {
+                        e.summary.push_str(&format!(" (complexity {})", info.complexity()));
+                    }
+                    // the extent of the block itself, not any macro
+                    // call it came from.
+                    let lo = cm.lookup_char_pos(info.span.lo).line;
+                    let hi = cm.lookup_char_pos(info.span.hi).line;
+                    let size = hi - lo + 1;
+                    if self.max_lines.map_or(false, |max| size > max) {
+                        e.summary.push_str(&format!(" (lines {}-{}, {} lines: oversized)",
+                                                    lo, hi, size));
+                        oversized += 1;
+                    } else if self.verbose {
+                        e.summary.push_str(&format!(" (lines {}-{})", lo, hi));
+                    }
+                    if is_suppressed {
+                        e.summary.push_str(" (suppressed)");
+                    }
+                    if self.lint_anomalies && forbidden {
+                        e.summary.push_str(&format!(" (anomaly: unsafe_code is {})",
+                                                    lint.as_ref().unwrap().0));
+                    }
+                    if let Some(ref coverage) = self.coverage {
+                        let hi = cm.lookup_char_pos(info.span.hi).line;
+                        if coverage.executed(&e.file, e.line, hi) == Some(false) {
+                            e.summary.push_str(" (untested)");
+                            e.untested = true;
+                        }
+                    }
 
                     // and the individual unsafe actions within each block
                     // (in source order)
+                    if self.macro_spans == MacroSpans::CallSite {
+                        v = v.into_iter().map(|s| macros::call_site(cm, s)).collect();
+                    }
                     v.sort_by(|a, b| a.lo.to_usize().cmp(&b.lo.to_usize()));
 
                     let mut seen = HashSet::new();
@@ -139,25 +1028,830 @@ impl Session {
                                 if !seen.contains(&t) {
                                     seen.insert(t);
                                     let line = lines.file.get_line(line_num).unwrap();
-                                    println!("{}", line);
+                                    e.lines.push(line.to_string());
                                 }
                             }
-                            _ => { println!("no lines"); }
+                            _ => { e.lines.push("no lines".to_string()); }
+                        }
+                    }
+                    if self.verbose && (self.wanted("asm") || self.wanted("asm_mem")) {
+                        for &(_, ref details) in &info.asm_details {
+                            e.lines.push(format!("    asm {}", details));
+                        }
+                    }
+                    if !info.crates.is_empty() {
+                        let crates = info.crates.iter().map(|c| &**c).collect::<Vec<_>>();
+                        e.lines.push(format!("    calls into: {}", crates.connect(", ")));
+                    }
+                    if self.wanted("ffi") {
+                        for call in &info.ffi_calls {
+                            let line = match call.lib {
+                                Some(ref lib) => format!("    ffi call into lib{}: {}",
+                                                         lib, call.name),
+                                None => format!("    ffi call: {}", call.name),
+                            };
+                            if !e.lines.contains(&line) {
+                                e.lines.push(line)
+                            }
+                            for &(ref callback, abi) in &call.callbacks {
+                                e.lines.push(format!("    callback {} (extern \"{}\") passed to {}",
+                                                     callback, abi.name(), call.name));
+                            }
+                        }
+                    }
+                    if self.max_results.is_some() {
+                        biggest.push((wanted, e));
+                    } else {
+                        entries.push(e);
+                    }
+                    reported += 1;
+                }
+            }
+
+            let mut not_shown = 0;
+            if let Some(max) = self.max_results {
+                // stable, so ties stay in their original order.
+                biggest.sort_by(|a, b| b.0.cmp(&a.0));
+                not_shown = biggest.len().saturating_sub(max);
+                biggest.truncate(max);
+                entries.extend(biggest.into_iter().map(|(_, e)| e));
+            }
+
+            if self.nonffi {
+                for &(span, ref desc) in &visitor.allocators {
+                    entries.push(entry(span, format!("allocator {}", desc)));
+                }
+            }
+
+            if self.extern_items {
+                for item in &visitor.foreign_items {
+                    entries.push(entry(item.span,
+                                       format!("extern \"{}\" {}",
+                                               item.abi.name(), item.sig)));
+                }
+            }
+
+            if self.exports {
+                for f in &visitor.exported_fns {
+                    entries.push(entry(f.span,
+                                       format!("exported extern \"{}\" fn {}{}",
+                                               f.abi.name(), f.name,
+                                               if f.no_mangle {" (no_mangle)"} else {""})));
+                }
+                for &(span, ref sig) in &visitor.exported_statics {
+                    entries.push(entry(span, format!("exported {} (no_mangle)", sig)));
+                }
+            }
+
+            if self.unsafe_traits {
+                let location = |span: Span| {
+                    let lo = cm.lookup_char_pos_adj(span.lo);
+                    format!("{}:{}:{}", lo.filename, lo.line, lo.col.to_usize() + 1)
+                };
+                for m in &visitor.unsafe_trait_methods {
+                    let mut e = entry(m.span, format!("unsafe trait method {}::{}",
+                                                      m.trait_path, m.name));
+                    for i in visitor.unsafe_method_impls.iter()
+                        .filter(|i| i.trait_path == m.trait_path && i.name == m.name) {
+                        e.lines.push(format!("    implemented for {} at {}",
+                                             i.self_ty.as_ref().unwrap(), location(i.span)));
+                    }
+                    entries.push(e);
+                }
+                for i in &visitor.unsafe_method_impls {
+                    let mut e = entry(i.span, format!("impl of unsafe trait method {}::{} for {}",
+                                                      i.trait_path, i.name,
+                                                      i.self_ty.as_ref().unwrap()));
+                    if let Some(m) = visitor.unsafe_trait_methods.iter()
+                        .find(|m| m.trait_path == i.trait_path && m.name == i.name) {
+                        e.lines.push(format!("    declared at {}", location(m.span)));
+                    }
+                    entries.push(e);
+                }
+            }
+
+            if self.target_features {
+                for &(span, ref path, ref features) in &visitor.target_feature_fns {
+                    entries.push(entry(span, format!("target_feature fn {} ({})",
+                                                     path, features.connect(", "))));
+                }
+            }
+
+            if self.macros {
+                for (span, name) in macros::macro_unsafes(tcx.map.krate()) {
+                    entries.push(entry(span, format!("unsafe in macro_rules! {}", name)));
+                }
+            }
+
+            if self.static_muts {
+                for s in &visitor.static_muts {
+                    let escapes = visitor.static_mut_escapes.iter()
+                        .filter(|&&(id, _)| id == s.id)
+                        .collect::<Vec<_>>();
+                    let mut reasons = Vec::new();
+                    if s.needs_drop { reasons.push("has a destructor") }
+                    if s.has_refs { reasons.push("contains references") }
+                    if !escapes.is_empty() { reasons.push("address passed to ffi") }
+                    if reasons.is_empty() { continue }
+
+                    let mut e = entry(s.span, format!("static mut {}: {} ({})",
+                                                      s.path, s.ty, reasons.connect(", ")));
+                    for &&(_, call) in &escapes {
+                        let lo = cm.lookup_char_pos_adj(call.lo);
+                        e.lines.push(format!("    passed to ffi at {}:{}:{}",
+                                             lo.filename, lo.line, lo.col.to_usize() + 1));
+                    }
+                    entries.push(e);
+                }
+            }
+
+            if self.thin_wrappers {
+                for &(span, ref name) in &visitor.thin_wrappers {
+                    entries.push(entry(span, format!("thin_wrapper fn {}", name)));
+                }
+            }
+
+            if self.attrs {
+                for &(span, ref name) in &visitor.attrs {
+                    entries.push(entry(span, format!("attr #[{}]", name)));
+                }
+            }
+
+            if self.wanted("unsafe_cell_get") {
+                for &(span, ref path) in &visitor.unsafe_cell_gets {
+                    entries.push(entry(span, format!("UnsafeCell::get in safe code in `{}`",
+                                                     path)));
+                }
+            }
+
+            if self.taint_exec {
+                for &(span, ref path) in &visitor.exec_calls {
+                    entries.push(entry(span, format!("exec call to `{}`", path)));
+                }
+            }
+
+            if self.fuzz_targets {
+                // a `pub fn` in a private module can't be called by the
+                // harness.
+                for &(span, id, ref path, ref args) in &visitor.fuzz_targets {
+                    if !exported.contains(&id) { continue }
+                    let mut e = entry(span, format!("fuzz target {}::{}({})",
+                                                    crate_name, path, args.connect(", ")));
+                    e.lines = fuzz_harness(crate_name, path, args);
+                    entries.push(e);
+                }
+            }
+
+            if self.miri {
+                let mut fns = BTreeMap::new();
+                for (_, info) in visitor.unsafes.iter().filter(|&(_, i)| !i.compiler) {
+                    fns.entry(&info.item_path).or_insert(info.span);
+                }
+                for (path, &span) in &fns {
+                    let mut tests = visitor.test_calls.iter()
+                        .filter(|&&(_, ref callee)| callee == *path)
+                        .map(|&(ref test, _)| test)
+                        .collect::<Vec<_>>();
+                    tests.sort();
+                    tests.dedup();
+
+                    let span = visitor.fn_spans.get(*path).cloned().unwrap_or(span);
+                    let mut e = entry(span, format!("miri {}", path));
+                    if tests.is_empty() {
+                        let name = path.replace("::", "_");
+                        e.lines.push("    #[test]".to_string());
+                        e.lines.push(format!("    fn miri_{}() {{", name));
+                        e.lines.push(format!("        // TODO: call `{}` so that its unsafe \
+                                              code runs", path));
+                        e.lines.push("    }".to_string());
+                    } else {
+                        for test in tests {
+                            e.lines.push(format!("    cargo miri test -- --exact {}", test));
                         }
                     }
+                    entries.push(e);
+                }
+            }
+
+            if self.hotspots {
+                let mut files = BTreeMap::new();
+                for (_, info) in visitor.unsafes.iter().filter(|&(_, i)| !i.compiler) {
+                    let n = info.kinds().iter().fold(0, |a, k| a + k.2.len());
+                    *files.entry(cm.lookup_char_pos(info.span.lo).file.name.clone())
+                        .or_insert(0) += n;
+                }
+                let files = files.into_iter().filter_map(|(file, n)| {
+                    git::commit_count(&file).map(|c| (file, c, n))
+                }).collect::<Vec<_>>();
+
+                let len = files.len() as f64;
+                let mean_commits = files.iter().fold(0, |a, f| a + f.1) as f64 / len;
+                let mean_actions = files.iter().fold(0, |a, f| a + f.2) as f64 / len;
+                for (file, commits, actions) in files {
+                    let hot = commits as f64 > mean_commits && actions as f64 > mean_actions;
+                    entries.push(Entry {
+                        file: file,
+                        line: 1,
+                        col: 1,
+                        summary: format!("{} commits, {} unsafe actions{}",
+                                         commits, actions,
+                                         if hot {" (hotspot)"} else {""}),
+                        lines: Vec::new(),
+                        untested: false,
+                    });
+                }
+            }
+
+            if let Some(threshold) = self.density {
+                let mut actions = BTreeMap::new();
+                for (_, info) in visitor.unsafes.iter() {
+                    if info.compiler { continue }
+                    let n = info.kinds().iter().fold(0, |a, k| a + k.2.len());
+                    *actions.entry(&info.item_path).or_insert(0) += n;
+                }
+                for (path, &n) in &actions {
+                    let span = match visitor.fn_spans.get(*path) {
+                        Some(span) => *span,
+                        None => continue
+                    };
+                    let lines = cm.lookup_char_pos(span.hi).line
+                        - cm.lookup_char_pos(span.lo).line + 1;
+                    let density = n as f64 / lines as f64;
+                    if density > threshold {
+                        entries.push(entry(span,
+                                           format!("fn {} with {} unsafe actions in {} \
+                                                    lines (density {:.2})",
+                                                   path, n, lines, density)));
+                    }
+                }
+            }
+
+            let mut out = String::new();
+            if let Some((ref level, ref manifest)) = lint {
+                // like the entries, relative in snapshots.
+                let manifest = if self.format == Format::Snapshot {
+                    report::relative_to(manifest, &env::current_dir().unwrap())
+                        .unwrap_or(manifest.clone())
+                } else {
+                    manifest.clone()
+                };
+                out.push_str(&format!("{}: unsafe_code = \"{}\"\n", manifest.display(), level));
+            }
+            // the unsafe blocks and functions have already been
+            // filtered, but not everything else.
+            entries.retain(|e| self.path_wanted(&e.file));
+            let untested = entries.iter().filter(|e| e.untested).count();
+            out.push_str(&report::render(self.format, self.collapse, entries));
+
+            if self.format == Format::Human {
+                if not_shown > 0 {
+                    out.push_str(&format!("... and {} more (see --max-results)\n", not_shown));
+                }
+                out.push('\n');
+                out.push_str(&totals(cm, &visitor));
+
+                // what to do about it, most pressing first.
+                let mut steps = Vec::new();
+                if !violations.is_empty() {
+                    steps.push(format!("remove {} unsafe actions in forbidden categories",
+                                       violations.len()));
+                }
+                if untested > 0 {
+                    steps.push(format!("write tests that run the {} untested unsafe blocks",
+                                       untested));
+                }
+                if unused + nested > 0 {
+                    steps.push(format!("remove {} unnecessary unsafe blocks and functions{}",
+                                       unused + nested,
+                                       if self.unused {""} else {" (see --unused)"}));
+                }
+                if oversized > 0 {
+                    steps.push(format!("shrink {} oversized unsafe blocks and functions to just \
+                                        the unsafe actions", oversized));
+                }
+                if self.thin_wrappers && !visitor.thin_wrappers.is_empty() {
+                    steps.push(format!("check the arguments of, or mark unsafe, {} thin \
+                                        wrappers", visitor.thin_wrappers.len()));
+                }
+                if reported > 0 {
+                    steps.push(format!("audit the {} unsafe blocks and functions above",
+                                       reported));
+                }
+                if suppressed > 0 {
+                    steps.push(format!("re-audit the {} suppressed unsafe blocks and functions \
+                                        when they change (see --show-suppressed)",
+                                       suppressed));
+                }
+                if !steps.is_empty() {
+                    out.push_str("\nnext steps:\n");
+                    for (i, step) in steps.iter().enumerate() {
+                        out.push_str(&format!("    {}. {}\n", i + 1, step));
+                    }
                 }
             }
+            (out, violations, fingerprints)
         })
     }
+
+    /// Whether `info` is picked by the options narrowing down which
+    /// unsafe blocks and functions are printed (everything but the
+    /// suppressions), whatever the output. `changed` caches the
+    /// `--since` diffs.
+    fn keep(&self, cm: &CodeMap, crate_name: &str, exported: &ExportedItems,
+            info: &visitor::NodeInfo,
+            changed: &mut HashMap<String, Vec<(usize, usize)>>) -> bool {
+        // compiler generated block, so we don't usually care.
+        if info.compiler && !self.include_generated { return false }
+        if self.no_tests && info.in_test { return false }
+        if self.pub_only && !exported.contains(&info.item_id) { return false }
+        if (self.fns_only && !info.is_fn) || (self.blocks_only && info.is_fn) {
+            return false
+        }
+        if !self.in_fn.as_ref().map_or(true, |re| re.is_match(info.item_name())) {
+            return false
+        }
+        if !self.modules.is_empty() {
+            let path = format!("{}::{}", crate_name, info.item_path);
+            if !self.modules.iter().any(|m| {
+                path == *m || path.starts_with(&format!("{}::", m))
+            }) {
+                return false
+            }
+        }
+        if self.macro_spans == MacroSpans::HideExternal &&
+            macros::from_external_macro(cm, info.span) {
+            return false
+        }
+        // the file it's reported in.
+        let span = if self.macro_spans == MacroSpans::CallSite {
+            macros::call_site(cm, info.span)
+        } else {
+            info.span
+        };
+        if !self.path_wanted(&cm.lookup_char_pos_adj(span.lo).filename) { return false }
+
+        if !self.calls_into.is_empty() &&
+            !self.calls_into.iter().any(|c| info.crates.contains(c)) {
+            return false
+        }
+        self.changed_since(cm, info.span, changed)
+    }
+
+    /// Whether `info` has been marked as audited, with a comment or
+    /// attribute, or by having its fingerprint in `--suppressions`.
+    fn suppressed(&self, cm: &CodeMap, info: &visitor::NodeInfo) -> bool {
+        suppress::suppressed(cm, info.span) ||
+            (!self.suppressions.is_empty() &&
+             self.suppressions.contains(&info.fingerprint(cm)))
+    }
+
+    /// The number of unsafe actions in `info` of the kinds being
+    /// printed.
+    fn wanted_actions(&self, info: &visitor::NodeInfo) -> usize {
+        info.kinds().iter()
+            .filter(|k| self.wanted(k.0))
+            .fold(0, |a, k| a + k.2.len())
+    }
+
+    /// Whether to print unsafe actions of the kind `name`: those
+    /// picked by `--only`, or else by `-n`/`-f`, less those in
+    /// `--exclude`.
+    fn wanted(&self, name: &str) -> bool {
+        if self.exclude.iter().any(|k| k == name) {
+            false
+        } else if !self.only.is_empty() {
+            self.only.iter().any(|k| k == name)
+        } else if name == "ffi" {
+            self.ffi
+        } else {
+            self.nonffi
+        }
+    }
+
+    /// `--by-file`: a line for each file with its number of unsafe
+    /// blocks and functions, and of the unsafe actions being printed
+    /// in them, most actions first.
+    fn by_file(&self, cm: &CodeMap, visitor: &visitor::UnsafeVisitor,
+               unsafes: &[&visitor::NodeInfo]) -> String {
+        let mut files = BTreeMap::new();
+        for info in unsafes {
+            let file = cm.lookup_char_pos(info.span.lo).file.name.clone();
+            let n = self.wanted_actions(info);
+            let counts = files.entry(file).or_insert((0, 0));
+            counts.0 += 1;
+            counts.1 += n;
+        }
+
+        let lines = unsafe_lines(cm, visitor);
+        let mut files = files.into_iter().collect::<Vec<_>>();
+        // stable, so ties stay in path order.
+        files.sort_by(|a, b| ((b.1).1, (b.1).0).cmp(&((a.1).1, (a.1).0)));
+        let mut out = String::new();
+        for (file, (blocks, actions)) in files {
+            let (inside, total) = lines.get(&file).cloned().unwrap_or((0, 0));
+            out.push_str(&format!("{}: {} unsafe blocks and functions, {} unsafe actions, \
+                                   {:.1}% of lines in unsafe code\n",
+                                  file, blocks, actions, percent(inside, total)));
+        }
+        out
+    }
+
+    /// `--tree`: the number of unsafe blocks and functions, and of the
+    /// unsafe actions being printed, in each module including its
+    /// submodules, indented under its parent.
+    fn tree(&self, crate_name: &str, unsafes: &[&visitor::NodeInfo]) -> String {
+        // keyed by the path's segments, so that each module comes
+        // straight after its parent.
+        let mut modules = BTreeMap::new();
+        for info in unsafes {
+            let n = self.wanted_actions(info);
+            let segments = info.module_path.split("::")
+                .filter(|s| !s.is_empty())
+                .collect::<Vec<_>>();
+            for i in 0..segments.len() + 1 {
+                let counts = modules.entry(segments[..i].to_vec()).or_insert((0, 0));
+                counts.0 += 1;
+                counts.1 += n;
+            }
+        }
+
+        let mut out = String::new();
+        for (segments, (blocks, actions)) in modules {
+            out.push_str(&format!("{:4$}{}: {} unsafe blocks and functions, {} unsafe actions\n",
+                                  "", segments.last().map_or(crate_name, |s| *s),
+                                  blocks, actions, segments.len() * 4));
+        }
+        out
+    }
+
+    /// `--top`: the `n` unsafe blocks and functions with the most
+    /// unsafe actions being printed, most first.
+    fn top(&self, cm: &CodeMap, crate_name: &str, unsafes: &[&visitor::NodeInfo],
+           n: usize) -> String {
+        let mut ranked = unsafes.iter().map(|&info| {
+            let actions = self.wanted_actions(info);
+            (actions, info)
+        }).filter(|&(actions, _)| actions > 0).collect::<Vec<_>>();
+        // ties in source order.
+        ranked.sort_by(|a, b| {
+            (b.0, a.1.span.lo.to_usize()).cmp(&(a.0, b.1.span.lo.to_usize()))
+        });
+        ranked.truncate(n);
+
+        let mut out = String::new();
+        for (actions, info) in ranked {
+            let lo = cm.lookup_char_pos(info.span.lo);
+            out.push_str(&format!("{:>6}  {}:{}: {} in `{}::{}`\n",
+                                  actions, lo.file.name, lo.line,
+                                  if info.is_fn {"fn"} else {"block"},
+                                  crate_name, info.item_path));
+        }
+        out
+    }
+
+    /// Whether `span` has lines added or changed since the `--since`
+    /// revision (always true without one), caching each file's
+    /// changes in `changed`.
+    fn changed_since(&self, cm: &CodeMap, span: Span,
+                     changed: &mut HashMap<String, Vec<(usize, usize)>>) -> bool {
+        let rev = match self.since {
+            Some(ref rev) => rev,
+            None => return true
+        };
+        let lo = cm.lookup_char_pos(span.lo);
+        let hi = cm.lookup_char_pos(span.hi).line;
+        let ranges = changed.entry(lo.file.name.clone()).or_insert_with(|| {
+            git::changed_lines(&lo.file.name, rev).unwrap_or(Vec::new())
+        });
+        ranges.iter().any(|&(first, last)| first <= hi && lo.line <= last)
+    }
+
+    /// `--stats`: the number of unsafe actions in each category being
+    /// printed (every built-in one, so that tables for different
+    /// crates line up).
+    fn stats(&self, crate_name: &str, unsafes: &[&visitor::NodeInfo]) -> String {
+        let mut counts = visitor::categories().into_iter()
+            .filter(|&(ref name, _)| self.wanted(name))
+            .map(|(name, _)| (name, 0))
+            .collect::<Vec<_>>();
+        for info in unsafes {
+            for &(name, _, spans) in info.kinds().iter().filter(|k| self.wanted(k.0)) {
+                match counts.iter().position(|c| c.0 == name) {
+                    Some(i) => counts[i].1 += spans.len(),
+                    None => counts.push((name.to_string(), spans.len())),
+                }
+            }
+        }
+
+        let width = counts.iter().fold(crate_name.len(), |w, c| cmp::max(w, c.0.len()));
+        let mut out = format!("{:<2$}  {:>6}\n", crate_name, "count", width);
+        for (name, count) in counts {
+            out.push_str(&format!("{:<2$}  {:>6}\n", name, count, width));
+        }
+        out
+    }
+
+    /// Whether to print findings in `file`, according to
+    /// `--include-path` and `--exclude-path`. Globs are matched
+    /// against the path relative to the current directory.
+    fn path_wanted(&self, file: &str) -> bool {
+        let cwd = env::current_dir().unwrap();
+        let path = report::relative_to(Path::new(file), &cwd)
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or(file.to_string());
+        (self.include_paths.is_empty() ||
+         self.include_paths.iter().any(|g| glob::matches(g, &path))) &&
+            !self.exclude_paths.iter().any(|g| glob::matches(g, &path))
+    }
+
+    /// Report the unsafe blocks and functions that only exist when
+    /// compiling with one of the feature sets `a` and `b`.
+    fn compare_features(&self, path: PathBuf, a: &[String], b: &[String]) -> String {
+        let unsafes = |features: &[String]| {
+            let cfg = features.iter().map(|f| format!("feature=\"{}\"", f)).collect();
+            let (path, search_paths, externs) = (path.clone(), self.search_paths.clone(),
+                                                 self.externs.clone());
+            let (detectors, audited, extended) = (self.detectors.clone(), self.audited.clone(),
+                                                  self.extended);
+            // the compiler has all sorts of thread locals, so each
+            // compilation gets its own thread.
+            thread::spawn(move || {
+                get_ast(path, cfg, false, search_paths, externs, |tcx, _, crate_name| {
+                    let cm = tcx.sess.codemap();
+                    let mut visitor = visitor::UnsafeVisitor::new(tcx, crate_name);
+                    visitor.detectors = detectors.clone();
+                    visitor.audited = audited.clone();
+                    visitor.extended = extended;
+                    visitor.check_crate(tcx.map.krate());
+
+                    // NodeIds differ between the two compilations, so
+                    // match things up by fingerprint.
+                    visitor.unsafes.values().filter(|i| !i.compiler).map(|info| {
+                        let lo = cm.lookup_char_pos_adj(info.span.lo);
+                        (info.fingerprint(cm),
+                         (lo.filename, lo.line, lo.col.to_usize() + 1,
+                          format!("{} with {:?}", if info.is_fn {"fn"} else {"block"}, *info)))
+                    }).collect::<Vec<_>>()
+                })
+            }).join().unwrap()
+        };
+
+        // each block once, with the feature sets it's compiled under.
+        let configs = [a, b];
+        let mut merged = BTreeMap::new();
+        for (i, features) in configs.iter().enumerate() {
+            for (fingerprint, found) in unsafes(*features) {
+                let present = &mut merged.entry(fingerprint).or_insert((found, Vec::new())).1;
+                if present.last() != Some(&i) {
+                    present.push(i)
+                }
+            }
+        }
+
+        let mut entries = Vec::new();
+        for (_, ((file, line, col, summary), present)) in merged {
+            if present.len() == configs.len() { continue }
+            let features = present.iter()
+                .map(|&i| format!("`{}`", configs[i].connect(",")))
+                .collect::<Vec<_>>();
+            entries.push(Entry {
+                file: file,
+                line: line,
+                col: col,
+                summary: format!("{} only with features {}", summary, features.connect("; ")),
+                lines: Vec::new(),
+                untested: false,
+            });
+        }
+        report::render(self.format, self.collapse, entries)
+    }
+}
+
+/// Write a `--progress json` event to stderr.
+fn progress(event: &str, fields: Vec<(&str, Json)>) {
+    let mut o = BTreeMap::new();
+    o.insert("event".to_string(), event.to_json());
+    for (k, v) in fields {
+        o.insert(k.to_string(), v);
+    }
+    let _ = writeln!(&mut io::stderr(), "{}", Json::Object(o));
+}
+
+/// The value of the option `opt`, if given, exiting with an error
+/// describing it as `what` if it isn't a valid `T`.
+fn parse_opt<T: FromStr>(matches: &getopts::Matches, name: &str, opt: &str,
+                         what: &str) -> Option<T> {
+    matches.opt_str(opt).map(|s| {
+        s.parse::<T>().unwrap_or_else(|_| {
+            let _ = writeln!(&mut io::stderr(), "{}: invalid {} `{}`", name, what, s);
+            process::exit(2)
+        })
+    })
+}
+
+/// Read a `--detectors` file: `path = category` lines, with `#`
+/// comments.
+fn read_detectors(path: &Path) -> io::Result<HashMap<String, String>> {
+    let mut contents = String::new();
+    try!(File::open(path).and_then(|mut f| f.read_to_string(&mut contents)));
+
+    let mut detectors = HashMap::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() { continue }
+
+        let mut parts = line.splitn(2, '=').map(|s| s.trim());
+        match (parts.next(), parts.next()) {
+            (Some(path), Some(category)) if !path.is_empty() && !category.is_empty() => {
+                detectors.insert(path.to_string(), category.to_string());
+            }
+            _ => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          format!("line {}: expected `path = category`",
+                                                  i + 1)))
+            }
+        }
+    }
+    Ok(detectors)
+}
+
+/// The entries of a `--suppressions` or `--audited` file, one per
+/// line; anything after a `#` is a comment.
+fn read_list(path: &Path) -> io::Result<HashSet<String>> {
+    let mut contents = String::new();
+    try!(File::open(path).and_then(|mut f| f.read_to_string(&mut contents)));
+
+    Ok(contents.lines()
+       .map(|line| line.split('#').next().unwrap().trim())
+       .filter(|line| !line.is_empty())
+       .map(|line| line.to_string())
+       .collect())
+}
+
+/// A `fuzz_target!` stub calling `krate::path` with arguments of type
+/// `args`: the first slice or string gets the fuzzer's input, and
+/// everything else is left for the author to fill in.
+fn fuzz_harness(krate: &str, path: &str, args: &[String]) -> Vec<String> {
+    let mut data_used = false;
+    let values = args.iter().map(|ty| {
+        if !data_used && ty == "&[u8]" {
+            data_used = true;
+            "data".to_string()
+        } else if !data_used && ty == "&str" {
+            data_used = true;
+            "s".to_string()
+        } else {
+            format!("/* TODO: {} */ Default::default()", ty)
+        }
+    }).collect::<Vec<_>>();
+
+    let mut lines = vec!["    fuzz_target!(|data: &[u8]| {".to_string()];
+    if values.iter().any(|v| v == "s") {
+        lines.push("        let s = match ::std::str::from_utf8(data) {".to_string());
+        lines.push("            Ok(s) => s,".to_string());
+        lines.push("            Err(_) => return,".to_string());
+        lines.push("        };".to_string());
+    }
+    lines.push(format!("        let _ = {}::{}({});", krate, path, values.connect(", ")));
+    lines.push("    });".to_string());
+    lines
+}
+
+/// Summarise the unsafety in each item, in the form of rustdoc's item
+/// index: `{"crate": ..., "index": {"crate::path::item": {...}}}`.
+fn item_index(crate_name: &str, unsafes: &[&visitor::NodeInfo]) -> Json {
+    // (is an unsafe fn, number of unsafe blocks, count of each kind,
+    // external crates called into, highest complexity)
+    let mut items = BTreeMap::new();
+    for info in unsafes {
+        let path = if info.item_path.is_empty() {
+            crate_name.to_string()
+        } else {
+            format!("{}::{}", crate_name, info.item_path)
+        };
+        let item = items.entry(path)
+            .or_insert((false, 0u64, BTreeMap::new(), BTreeSet::new(), 0));
+        if info.is_fn {
+            item.0 = true
+        } else {
+            item.1 += 1
+        }
+        for &(name, _, spans) in info.kinds().iter().filter(|k| !k.2.is_empty()) {
+            *item.2.entry(name.to_string()).or_insert(0u64) += spans.len() as u64;
+        }
+        item.3.extend(info.crates.iter().cloned());
+        item.4 = cmp::max(item.4, info.complexity());
+    }
+
+    let index = items.into_iter().map(|(path, (is_fn, blocks, kinds, crates, complexity))| {
+        let mut o = BTreeMap::new();
+        o.insert("unsafe_fn".to_string(), is_fn.to_json());
+        o.insert("unsafe_blocks".to_string(), blocks.to_json());
+        o.insert("unsafe_operations".to_string(),
+                 kinds.values().fold(0, |a, &b| a + b).to_json());
+        o.insert("kinds".to_string(), kinds.to_json());
+        o.insert("crates".to_string(), crates.into_iter().collect::<Vec<_>>().to_json());
+        o.insert("complexity".to_string(), complexity.to_json());
+        (path, Json::Object(o))
+    }).collect();
+
+    let mut krate = BTreeMap::new();
+    krate.insert("crate".to_string(), crate_name.to_json());
+    krate.insert("index".to_string(), Json::Object(index));
+    Json::Object(krate)
+}
+
+/// `--format heatmap`: `file,line,actions` rows for each line with
+/// unsafe actions, for overlaying on the source.
+fn heatmap(cm: &CodeMap, unsafes: &[&visitor::NodeInfo]) -> String {
+    let mut counts = BTreeMap::new();
+    for info in unsafes {
+        for &(_, _, spans) in &info.kinds() {
+            for span in spans {
+                let lo = cm.lookup_char_pos(span.lo);
+                *counts.entry((lo.file.name.clone(), lo.line)).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let mut out = String::new();
+    for ((file, line), count) in counts {
+        let file = if file.contains(',') || file.contains('"') {
+            format!("\"{}\"", file.replace("\"", "\"\""))
+        } else {
+            file
+        };
+        out.push_str(&format!("{},{},{}\n", file, line, count));
+    }
+    out
+}
+
+/// For each of the crate's source files, the number of lines inside
+/// unsafe blocks or functions, and its total number of lines.
+fn unsafe_lines(cm: &CodeMap, visitor: &visitor::UnsafeVisitor)
+                -> BTreeMap<String, (usize, usize)> {
+    let mut lines = BTreeSet::new();
+    for info in visitor.unsafes.values().filter(|i| !i.compiler) {
+        let lo = cm.lookup_char_pos(info.span.lo);
+        let hi = cm.lookup_char_pos(info.span.hi).line;
+        for line in lo.line..hi + 1 {
+            lines.insert((lo.file.name.clone(), line));
+        }
+    }
+
+    let mut files = cm.files.borrow().iter()
+        .filter(|fm| !fm.is_imported() && !fm.name.starts_with("<"))
+        .map(|fm| (fm.name.clone(), (0, fm.lines.borrow().len())))
+        .collect::<BTreeMap<_, _>>();
+    for (file, _) in lines {
+        if let Some(counts) = files.get_mut(&file) {
+            counts.0 += 1
+        }
+    }
+    files
+}
+
+/// `n` as a percentage of `total`.
+fn percent(n: usize, total: usize) -> f64 {
+    if total == 0 { 0.0 } else { 100.0 * n as f64 / total as f64 }
+}
+
+/// The number of unsafe blocks and functions in the crate, and of
+/// each kind of unsafe action inside them.
+fn totals(cm: &CodeMap, visitor: &visitor::UnsafeVisitor) -> String {
+    let (mut blocks, mut fns) = (0, 0);
+    let mut counts = BTreeMap::new();
+    for info in visitor.unsafes.values().filter(|i| !i.compiler) {
+        if info.is_fn { fns += 1 } else { blocks += 1 }
+        for &(_, desc, spans) in &info.kinds() {
+            if !spans.is_empty() {
+                *counts.entry(desc.to_string()).or_insert(0) += spans.len();
+            }
+        }
+    }
+
+    let (inside, total) = unsafe_lines(cm, visitor).values()
+        .fold((0, 0), |(a, b), &(inside, total)| (a + inside, b + total));
+    let mut out = format!("totals: {} unsafe blocks, {} unsafe fns, {} of {} lines \
+                           ({:.1}%) in unsafe code\n",
+                          blocks, fns, inside, total, percent(inside, total));
+    for (desc, count) in counts {
+        out.push_str(&format!("    {} {}\n", count, desc));
+    }
+    out
 }
 
 pub type Externs = HashMap<String, Vec<String>>;
 
 /// Extract the expanded ast of a krate, along with the codemap which
-/// connects source code locations to the actual code.
-fn get_ast<F: Fn(&ty::ctxt)>(path: PathBuf,
-                             search_paths: SearchPaths, externs: Externs,
-                             f: F) {
+/// connects source code locations to the actual code, and the items
+/// visible outside it. With `test`, it's compiled as a test harness,
+/// as `rustc --test` would.
+fn get_ast<T, F: Fn(&ty::ctxt, &ExportedItems, &str) -> T>(path: PathBuf, cfg: Vec<String>,
+                                                      test: bool,
+                                                      search_paths: SearchPaths,
+                                                      externs: Externs,
+                                                      f: F) -> T {
     use syntax::diagnostic;
 
     // cargo culted from rustdoc :(
@@ -167,6 +1861,12 @@ fn get_ast<F: Fn(&ty::ctxt)>(path: PathBuf,
         maybe_sysroot: Some(env::current_exe().unwrap().parent().unwrap().to_owned()),
         externs: externs,
         search_paths: search_paths,
+        // `--cfg` style, e.g. `feature="simd"`.
+        cfg: config::parse_cfgspecs(cfg),
+        // `--miri` needs the `#[test]` fns and `#[cfg(test)]` code,
+        // which are otherwise stripped before we see them; everything
+        // else looks at the crate as it's normally built.
+        test: test,
         .. config::basic_options().clone()
     };
 
@@ -180,11 +1880,22 @@ fn get_ast<F: Fn(&ty::ctxt)>(path: PathBuf,
 
     let cfg = config::build_configuration(&sess);
 
-    let mut controller = driver::CompileController::basic();
-    controller.after_analysis = driver::PhaseController {
-        stop: rustc_driver::Compilation::Stop,
-        callback: Box::new(|state| f(state.tcx.unwrap()))
-    };
+    let result = RefCell::new(None);
+    {
+        let mut controller = driver::CompileController::basic();
+        controller.after_analysis = driver::PhaseController {
+            stop: rustc_driver::Compilation::Stop,
+            callback: Box::new(|state| {
+                let tcx = state.tcx.unwrap();
+                let crate_name = link::find_crate_name(Some(&tcx.sess),
+                                                       &tcx.map.krate().attrs,
+                                                       &input);
+                let exported = &state.analysis.unwrap().exported_items;
+                *result.borrow_mut() = Some(f(tcx, exported, &crate_name))
+            })
+        };
 
-    driver::compile_input(sess, cfg, &input, &None, &None, None, controller);
+        driver::compile_input(sess, cfg, &input, &None, &None, None, controller);
+    }
+    result.into_inner().unwrap()
 }
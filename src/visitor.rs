@@ -1,15 +1,81 @@
 use rustc::middle::{ty, def};
 use rustc::middle::ty::MethodCall;
 
-use syntax::{ast, ast_util, ast_map};
-use syntax::codemap::Span;
+use syntax::{abi, ast, ast_util, ast_map, attr};
+use syntax::attr::AttrMetaMethods;
+use syntax::codemap::{mk_sp, BytePos, CodeMap, Span, DUMMY_SP};
 use syntax::parse::token;
+use syntax::print::pprust;
 use syntax::visit;
 use syntax::visit::Visitor;
 
 use std::fmt;
+use std::hash::{Hash, Hasher, SipHasher};
 use std::mem::replace;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+/// Functions that move values in and out of memory through raw
+/// pointers.
+static RAW_MEM_FNS: &'static [&'static str] = &[
+    "core::ptr::read",
+    "core::ptr::write",
+    "core::ptr::replace",
+    "core::ptr::swap",
+];
+
+/// Functions that make a slice out of a pointer and a length.
+static RAW_SLICE_FNS: &'static [&'static str] = &[
+    "core::slice::from_raw_parts",
+    "core::slice::from_raw_parts_mut",
+];
+
+/// Functions that take ownership of an allocation given its pointer,
+/// length and capacity.
+static RAW_PARTS_FNS: &'static [&'static str] = &[
+    "collections::vec::Vec::from_raw_parts",
+    "collections::string::String::from_raw_parts",
+];
+
+/// Functions that modify the process's environment, which races with
+/// any other thread reading it (e.g. via `getenv` in C code). They
+/// are unsafe on newer toolchains, so are tracked whether or not
+/// they're marked `unsafe`.
+static ENV_FNS: &'static [&'static str] = &[
+    "std::env::set_var",
+    "std::env::remove_var",
+];
+
+/// Functions that tell the optimiser something can't happen, and so
+/// encode a logic invariant of the program.
+static HINT_UNCHECKED_FNS: &'static [&'static str] = &[
+    "core::hint::unreachable_unchecked",
+    "core::intrinsics::unreachable",
+    "core::option::Option::unwrap_unchecked",
+    "core::result::Result::unwrap_unchecked",
+];
+
+/// Functions that aren't necessarily unsafe, but that undermine the
+/// assumptions of nearby unsafe code (leaking, raw ownership, lengths),
+/// only tracked with `--extended`.
+static EXTENDED_FNS: &'static [&'static str] = &[
+    "core::mem::forget",
+    "core::mem::ManuallyDrop::new",
+    "alloc::rc::Rc::into_raw",
+    "alloc::arc::Arc::into_raw",
+    "alloc::boxed::Box::into_raw",
+    "collections::vec::Vec::set_len",
+];
+
+/// Foreign functions that run another program.
+static EXEC_FNS: &'static [&'static str] = &[
+    "system", "popen", "execl", "execle", "execlp", "execv", "execve", "execvp", "execvpe",
+];
+
+/// Whether the function with path `path` runs another program.
+fn is_exec(path: &str, foreign: bool) -> bool {
+    path == "std::process::Command::new" ||
+        (foreign && path.rsplit("::").next().map_or(false, |name| EXEC_FNS.contains(&name)))
+}
 
 fn type_is_unsafe_function(ty: ty::Ty) -> bool {
     match ty.sty {
@@ -18,13 +84,150 @@ fn type_is_unsafe_function(ty: ty::Ty) -> bool {
     }
 }
 
+/// The name and description of each built-in category of unsafe
+/// action.
+pub fn categories() -> Vec<(String, String)> {
+    NodeInfo::new(DUMMY_SP, false, false, String::new()).kinds().iter()
+        .map(|&(name, desc, _)| (name.to_string(), desc.to_string()))
+        .collect()
+}
+
+/// The words of the source of `span`, ignoring layout.
+fn collapsed_source(cm: &CodeMap, span: Span) -> Vec<String> {
+    cm.span_to_snippet(span).unwrap_or(String::new())
+        .split_whitespace().map(|w| w.to_string()).collect()
+}
+
+/// Whether `attrs` includes `#[cfg(test)]`.
+fn is_cfg_test(attrs: &[ast::Attribute]) -> bool {
+    attrs.iter()
+        .filter(|a| a.check_name("cfg"))
+        .filter_map(|a| a.meta_item_list())
+        .any(|items| items.iter().any(|mi| mi.check_name("test")))
+}
+
+/// A call to a foreign function.
+pub struct FfiCall {
+    pub span: Span,
+    pub name: String,
+    /// The library named by `#[link]` on the `extern` block
+    /// declaring the function.
+    pub lib: Option<String>,
+    /// The Rust functions passed to it, with their ABIs.
+    pub callbacks: Vec<(String, abi::Abi)>,
+}
+
+/// Whether a fuzzer can easily produce values of `ty`: primitives,
+/// `&str` and `&[u8]`.
+fn type_is_fuzzable(ty: ty::Ty) -> bool {
+    match ty.sty {
+        ty::ty_bool | ty::ty_char | ty::ty_int(_) | ty::ty_uint(_) | ty::ty_float(_) => true,
+        ty::ty_rptr(_, ty::mt { ty, mutbl: ast::MutImmutable }) => match ty.sty {
+            ty::ty_str => true,
+            ty::ty_vec(elem, None) => match elem.sty {
+                ty::ty_uint(ast::TyU8) => true,
+                _ => false
+            },
+            _ => false
+        },
+        _ => false
+    }
+}
+
+/// Find each `cfg(miri)`, `cfg!(miri)`, `cfg(not(miri))`, etc. in
+/// `text`, returning the offset of their `cfg`s.
+fn find_cfg_miri(text: &str) -> Vec<usize> {
+    let mut found = Vec::new();
+    let mut start = 0;
+    while let Some(i) = text[start..].find("cfg") {
+        let at = start + i;
+        let rest = text[at + 3..].trim_left();
+        let rest = if rest.starts_with("!") { rest[1..].trim_left() } else { rest };
+        // the first `)` closes at least the innermost predicate, which
+        // is enough for the simple forms people actually write.
+        if rest.starts_with("(") && rest.split(')').next().unwrap().contains("miri") {
+            found.push(at)
+        }
+        start = at + 3;
+    }
+    found
+}
+
 pub struct NodeInfo {
     pub span: Span,
     pub is_fn: bool,
     pub compiler: bool,
+    /// The path of the item containing this, relative to the crate
+    /// root.
+    pub item_path: String,
+    /// The id of that item.
+    pub item_id: ast::NodeId,
+    /// The path of the module containing this, relative to the crate
+    /// root (empty for the root itself).
+    pub module_path: String,
+    /// Whether this is evaluated at compile time, e.g. in the
+    /// initializer of a `const` or `static`.
+    pub in_const: bool,
+    /// Whether this is in a `Drop` implementation, and so may run
+    /// during unwinding or on partially-moved-from values.
+    pub in_drop: bool,
+    /// Whether this is in a `#[test]` function or `#[cfg(test)]`
+    /// item, i.e. code that doesn't ship.
+    pub in_test: bool,
+    /// The path of the external trait this is in an implementation
+    /// of, and the crate defining it (and so its safety contract).
+    pub external_trait: Option<(String, String)>,
+    /// `cfg(miri)` conditions inside this, where the code Miri checks
+    /// differs from what really runs.
+    pub cfg_miri: Vec<Span>,
+    /// Calls to audited functions, which aren't reported but still
+    /// need the `unsafe`.
+    pub audited_calls: Vec<Span>,
+    /// How many identical (by `fingerprint`) blocks come before this
+    /// one in the same item.
+    pub occurrence: usize,
+    /// Every action in this that needs `unsafe`, whatever category it
+    /// is reported in (the rest, like casts, are safe but notable).
+    pub needs_unsafe: Vec<Span>,
+    /// Closures written inside this.
+    pub closures: Vec<Span>,
+    /// `unsafe` blocks written inside this, which are redundant.
+    pub nested: Vec<Span>,
+    /// Unsafe method calls through trait objects (these are also in
+    /// `unsafe_call`).
+    pub dyn_call: Vec<Span>,
+    /// Calls to functions in user-defined categories, by category.
+    pub custom: BTreeMap<String, Vec<Span>>,
+    /// The external crates containing the unsafe functions called.
+    pub crates: BTreeSet<String>,
     pub ffi: Vec<Span>,
+    pub ffi_calls: Vec<FfiCall>,
     pub raw_deref: Vec<Span>,
+    pub raw_mem: Vec<Span>,
+    /// Raw pointers created by borrowing a place.
+    pub raw_borrow: Vec<Span>,
+    /// Slices made from a pointer and a length.
+    pub raw_slice: Vec<Span>,
+    /// `Vec`s and `String`s rebuilt from a pointer, length and
+    /// capacity.
+    pub raw_parts: Vec<Span>,
+    pub unsafe_cell_get: Vec<Span>,
+    pub extended: Vec<Span>,
+    pub unchecked_assumption: Vec<Span>,
+    /// `unreachable_unchecked` and `unwrap_unchecked`.
+    pub hint_unchecked: Vec<Span>,
+    /// Values made without checking their invariant, like
+    /// `NonNull::new_unchecked`.
+    pub unchecked_constructor: Vec<Span>,
+    /// Modifications of environment variables.
+    pub env: Vec<Span>,
+    /// Calls to `#[target_feature]` functions, which are unsafe
+    /// because the CPU might not support them.
+    pub target_feature_call: Vec<Span>,
     pub static_mut: Vec<Span>,
+    /// References to a `static mut`, which can alias with every
+    /// other access to it for as long as they live.
+    pub static_mut_ref: Vec<Span>,
     pub unsafe_call: Vec<Span>,
     pub transmute: Vec<Span>,
     pub transmute_imm_to_mut: Vec<Span>,
@@ -32,67 +235,370 @@ pub struct NodeInfo {
     // these are only picked up with written in unsafe blocks, but *const
     // as *mut is legal anywhere.
     pub cast_raw_ptr_const_to_mut: Vec<Span>,
+    pub fn_ptr_cast: Vec<Span>,
+    pub int_to_ptr: Vec<Span>,
+    pub ptr_to_int: Vec<Span>,
+    /// `ptr as usize as *mut T`, which loses the pointer's provenance.
+    pub provenance_roundtrip: Vec<Span>,
     pub asm: Vec<Span>,
+    /// `asm!`s that may write to memory.
+    pub asm_mem: Vec<Span>,
+    /// Rust functions passed to foreign code, to be called back.
+    pub callback: Vec<Span>,
+    /// The clobbers and options of each `asm!`.
+    pub asm_details: Vec<(Span, String)>,
 }
 
 impl NodeInfo {
-    fn new(span: Span, is_fn: bool, compiler: bool) -> NodeInfo {
+    fn new(span: Span, is_fn: bool, compiler: bool, item_path: String) -> NodeInfo {
         NodeInfo {
             span: span,
             is_fn: is_fn,
             compiler: compiler,
+            item_path: item_path,
+            item_id: ast::CRATE_NODE_ID,
+            module_path: String::new(),
+            in_const: false,
+            in_drop: false,
+            in_test: false,
+            external_trait: None,
+            cfg_miri: Vec::new(),
+            audited_calls: Vec::new(),
+            occurrence: 0,
+            needs_unsafe: Vec::new(),
+            closures: Vec::new(),
+            nested: Vec::new(),
+            dyn_call: Vec::new(),
+            custom: BTreeMap::new(),
+            crates: BTreeSet::new(),
             ffi: Vec::new(),
+            ffi_calls: Vec::new(),
             raw_deref: Vec::new(),
+            raw_mem: Vec::new(),
+            raw_borrow: Vec::new(),
+            raw_slice: Vec::new(),
+            raw_parts: Vec::new(),
+            unsafe_cell_get: Vec::new(),
+            extended: Vec::new(),
+            unchecked_assumption: Vec::new(),
+            hint_unchecked: Vec::new(),
+            unchecked_constructor: Vec::new(),
+            env: Vec::new(),
+            target_feature_call: Vec::new(),
             static_mut: Vec::new(),
+            static_mut_ref: Vec::new(),
             unsafe_call: Vec::new(),
             transmute: Vec::new(),
             transmute_imm_to_mut: Vec::new(),
             cast_raw_ptr_const_to_mut: Vec::new(),
-            asm: Vec::new()
+            fn_ptr_cast: Vec::new(),
+            int_to_ptr: Vec::new(),
+            ptr_to_int: Vec::new(),
+            provenance_roundtrip: Vec::new(),
+            asm: Vec::new(),
+            asm_mem: Vec::new(),
+            callback: Vec::new(),
+            asm_details: Vec::new(),
         }
     }
+
+    /// Every kind of unsafe action, as `(name, description, spans)`,
+    /// in the order they're printed.
+    pub fn kinds(&self) -> Vec<(&str, &str, &[Span])> {
+        let mut kinds = vec![("asm_mem", "asm writing memory", &self.asm_mem[..]),
+             ("asm", "asm", &self.asm[..]),
+             ("raw_deref", "deref", &self.raw_deref[..]),
+             ("ffi", "ffi", &self.ffi[..]),
+             ("callback", "ffi callback", &self.callback[..]),
+             ("raw_mem", "ptr read/write", &self.raw_mem[..]),
+             ("raw_borrow", "raw borrow", &self.raw_borrow[..]),
+             ("raw_slice", "slice from raw parts", &self.raw_slice[..]),
+             ("raw_parts", "Vec/String from raw parts", &self.raw_parts[..]),
+             ("unsafe_cell_get", "UnsafeCell::get", &self.unsafe_cell_get[..]),
+             ("extended", "hazardous safe call", &self.extended[..]),
+             ("unchecked_assumption", "unchecked assumption",
+              &self.unchecked_assumption[..]),
+             ("hint_unchecked", "unreachable/unwrap_unchecked", &self.hint_unchecked[..]),
+             ("unchecked_constructor", "unchecked constructor",
+              &self.unchecked_constructor[..]),
+             ("env", "env mutation", &self.env[..]),
+             ("target_feature_call", "target_feature call", &self.target_feature_call[..]),
+             ("static_mut", "static mut", &self.static_mut[..]),
+             ("static_mut_ref", "static mut reference", &self.static_mut_ref[..]),
+             ("transmute", "transmute", &self.transmute[..]),
+             ("transmute_imm_to_mut", "transmute & to &mut",
+              &self.transmute_imm_to_mut[..]),
+             ("cast_raw_ptr_const_to_mut", "cast *const to *mut",
+              &self.cast_raw_ptr_const_to_mut[..]),
+             ("fn_ptr_cast", "fn pointer cast", &self.fn_ptr_cast[..]),
+             ("int_to_ptr", "cast int to pointer", &self.int_to_ptr[..]),
+             ("ptr_to_int", "cast pointer to int", &self.ptr_to_int[..]),
+             ("provenance_roundtrip", "pointer round-trip through int",
+              &self.provenance_roundtrip[..]),
+             ("unsafe_call", "unsafe call", &self.unsafe_call[..])];
+        for (name, spans) in self.custom.iter() {
+            kinds.push((name, name, &spans[..]))
+        }
+        kinds
+    }
+
+    /// The name of the innermost item containing this, e.g. `foo` for
+    /// a block in `Bar::foo`.
+    pub fn item_name(&self) -> &str {
+        self.item_path.rsplit("::").next().unwrap()
+    }
+
+    /// How many different kinds of unsafe action this contains: code
+    /// mixing FFI, transmutes and raw derefs is harder to reason
+    /// about than code doing lots of one thing.
+    pub fn complexity(&self) -> usize {
+        self.kinds().iter().filter(|k| !k.2.is_empty()).count()
+    }
+
+    /// A key identifying this across compilations, e.g. under
+    /// different `cfg`s, where the `NodeId`s don't match up: a hash
+    /// of the containing item's path and the (whitespace-collapsed)
+    /// source, and which of the identical blocks in the same item
+    /// this is.
+    pub fn fingerprint(&self, cm: &CodeMap) -> String {
+        let mut hasher = SipHasher::new();
+        self.item_path.hash(&mut hasher);
+        self.is_fn.hash(&mut hasher);
+        for word in collapsed_source(cm, self.span) {
+            word.hash(&mut hasher);
+        }
+        // so that the first (usually only) one is unchanged.
+        if self.occurrence > 0 {
+            self.occurrence.hash(&mut hasher);
+        }
+        format!("{:016x}", hasher.finish())
+    }
+
+    /// The number of unsafe actions that are inside a closure.
+    pub fn via_closure(&self) -> usize {
+        self.kinds().iter().flat_map(|k| k.2.iter()).filter(|s| {
+            self.closures.iter().any(|c| c.lo <= s.lo && s.hi <= c.hi)
+        }).count()
+    }
 }
 impl fmt::Debug for NodeInfo {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         let mut first = true;
-        macro_rules! p ( ($fmt: tt, $name: ident) => {
-                if !self.$name.is_empty() {
-                    if !first {
-                        try!(write!(fmt, ", "));
-                    } else {
-                        first = false
-                    }
-                    try!(write!(fmt, concat!("{} ", $fmt), self.$name.len()))
-                }
-            });
-        p!("asm", asm);
-        p!("deref", raw_deref);
-        p!("ffi", ffi);
-        p!("static mut", static_mut);
-        p!("transmute", transmute);
-        p!("transmute & to &mut", transmute_imm_to_mut);
-        p!("cast *const to *mut", cast_raw_ptr_const_to_mut);
-        p!("unsafe call", unsafe_call);
-        // silence dead assign warning
-        if first {}
+        for &(_, desc, spans) in self.kinds().iter().filter(|k| !k.2.is_empty()) {
+            if !first {
+                try!(write!(fmt, ", "));
+            } else {
+                first = false
+            }
+            try!(write!(fmt, "{} {}", spans.len(), desc))
+        }
+        if !self.cfg_miri.is_empty() {
+            try!(write!(fmt, "{}{} cfg(miri)", if first {""} else {", "}, self.cfg_miri.len()))
+        }
+        if !self.dyn_call.is_empty() {
+            try!(write!(fmt, " ({} via dyn)", self.dyn_call.len()))
+        }
+        let closure = self.via_closure();
+        if closure > 0 {
+            try!(write!(fmt, " ({} via closure)", closure))
+        }
+        if !self.nested.is_empty() {
+            try!(write!(fmt, " ({} nested unsafe)", self.nested.len()))
+        }
         Ok(())
     }
 }
 
+/// A function or static declared inside an `extern` block.
+pub struct ForeignItemInfo {
+    pub span: Span,
+    pub abi: abi::Abi,
+    pub is_fn: bool,
+    /// The signature, as it would be written in the source.
+    pub sig: String,
+}
+
+impl ForeignItemInfo {
+    fn new(item: &ast::ForeignItem, abi: abi::Abi) -> ForeignItemInfo {
+        let (is_fn, sig) = match item.node {
+            ast::ForeignItemFn(ref decl, ref generics) => {
+                (true, pprust::fun_to_string(&**decl, ast::Unsafety::Normal,
+                                             item.ident, None, generics))
+            }
+            ast::ForeignItemStatic(ref ty, mutbl) => {
+                (false, format!("static {}{}: {}",
+                                if mutbl {"mut "} else {""},
+                                token::get_ident(item.ident),
+                                pprust::ty_to_string(&**ty)))
+            }
+        };
+        ForeignItemInfo {
+            span: item.span,
+            abi: abi,
+            is_fn: is_fn,
+            sig: sig,
+        }
+    }
+}
+
+/// A function that foreign code can call: one with a non-Rust ABI or
+/// marked `#[no_mangle]`.
+pub struct ExportedFnInfo {
+    pub span: Span,
+    pub name: String,
+    pub abi: abi::Abi,
+    pub no_mangle: bool,
+}
+
+/// An unsafe method declared in a trait, or a method implementing
+/// one.
+pub struct UnsafeMethodInfo {
+    pub span: Span,
+    /// The path of the trait, e.g. `mycrate::Raw`.
+    pub trait_path: String,
+    pub name: String,
+    /// The implementing type, for implementations.
+    pub self_ty: Option<String>,
+}
+
+/// A `static mut` item.
+pub struct StaticMutInfo {
+    pub span: Span,
+    pub id: ast::NodeId,
+    pub path: String,
+    /// The type, as written.
+    pub ty: String,
+    /// Whether the type has a destructor, which never runs.
+    pub needs_drop: bool,
+    /// Whether the type contains references, which can easily end up
+    /// dangling or aliased.
+    pub has_refs: bool,
+}
+
 pub struct UnsafeVisitor<'tcx, 'a: 'tcx> {
     tcx: &'tcx ty::ctxt<'a>,
+    crate_name: String,
+    /// User-defined categories for calls to particular functions, as
+    /// `path -> category`.
+    pub detectors: HashMap<String, String>,
+    /// Unsafe functions whose calls have already been audited, and so
+    /// aren't reported.
+    pub audited: HashSet<String>,
+    /// Whether to track the functions in `EXTENDED_FNS`.
+    pub extended: bool,
 
     /// Whether we're in an unsafe context.
     node_info: Option<(ast::NodeId, NodeInfo)>,
+    /// The path of the item we're currently inside.
+    item_path: String,
+    /// The id of that item.
+    item_id: ast::NodeId,
+    /// The path of the module we're currently inside.
+    module_path: String,
+    /// Whether we're in a constant expression.
+    in_const: bool,
+    /// Whether we're in an `impl Drop for ...`.
+    in_drop: bool,
+    /// The external trait (and its crate) of the `impl` we're in.
+    external_trait: Option<(String, String)>,
+    /// The operand of the innermost cast to a raw pointer.
+    raw_cast_operand: Option<ast::NodeId>,
+    /// The `static mut` path of the innermost `&STATIC`/`&mut STATIC`.
+    static_mut_borrow: Option<ast::NodeId>,
+    /// Pointer-to-int casts that are the first half of a round-trip
+    /// already counted, e.g. in `(ptr as usize + 1) as *mut T`.
+    roundtrip_operands: HashSet<ast::NodeId>,
+    /// Local variables (by binding) holding an integer derived from a
+    /// pointer, with the id and span of the cast it came from.
+    ptr_ints: HashMap<ast::NodeId, (ast::NodeId, Span)>,
+    /// The path of the `#[test]` function we're inside, if any.
+    test_fn: Option<String>,
+    /// Whether we're inside a `#[test]` or `#[cfg(test)]` item.
+    in_test: bool,
     pub unsafes: BTreeMap<ast::NodeId, NodeInfo>,
+    /// Everything declared in `extern` blocks, in source order.
+    pub foreign_items: Vec<ForeignItemInfo>,
+    pub exported_fns: Vec<ExportedFnInfo>,
+    /// `#[no_mangle]` statics, which foreign code can alias, as
+    /// `(span, signature)`.
+    pub exported_statics: Vec<(Span, String)>,
+    /// Public safe functions that just pass their arguments to an
+    /// unsafe function, as `(span, name)`.
+    pub thin_wrappers: Vec<(Span, String)>,
+    /// Safe functions containing unsafe code that only take fuzzable
+    /// arguments, as `(span, id, item path, argument types)`; only
+    /// those visible outside the crate can be fuzzed.
+    pub fuzz_targets: Vec<(Span, ast::NodeId, String, Vec<String>)>,
+    /// Calls to local functions from `#[test]` functions, as `(test
+    /// path, callee path)`.
+    pub test_calls: Vec<(String, String)>,
+    /// Calls anywhere in the crate that run another program, as
+    /// `(span, callee path)`.
+    pub exec_calls: Vec<(Span, String)>,
+    /// Calls to `UnsafeCell::get` outside unsafe code (the pointer is
+    /// usually only used in an `unsafe` block later), as `(span, item
+    /// path)`; those inside are in their `NodeInfo`.
+    pub unsafe_cell_gets: Vec<(Span, String)>,
+    /// Items with attributes that affect soundness, as `(item span,
+    /// attribute)`.
+    pub attrs: Vec<(Span, String)>,
+    /// The span of each function and method, by item path.
+    pub fn_spans: HashMap<String, Span>,
+    /// Unsafe methods declared in traits, i.e. the contracts.
+    pub unsafe_trait_methods: Vec<UnsafeMethodInfo>,
+    /// Implementations of unsafe trait methods, i.e. where those
+    /// contracts have to be fulfilled.
+    pub unsafe_method_impls: Vec<UnsafeMethodInfo>,
+    /// Functions with `#[target_feature]`, as `(span, item path,
+    /// features)`.
+    pub target_feature_fns: Vec<(Span, String, Vec<String>)>,
+    pub static_muts: Vec<StaticMutInfo>,
+    /// `GlobalAlloc` implementations and `#[global_allocator]`
+    /// statics, as `(span, description)`.
+    pub allocators: Vec<(Span, String)>,
+    /// FFI calls passed the address of a local `static mut`, as
+    /// `(static, call span)`.
+    pub static_mut_escapes: Vec<(ast::NodeId, Span)>,
 }
 
 impl<'tcx, 'a> UnsafeVisitor<'tcx, 'a> {
-    pub fn new(tcx: &'tcx ty::ctxt<'a>) -> UnsafeVisitor<'tcx, 'a> {
+    pub fn new(tcx: &'tcx ty::ctxt<'a>, crate_name: &str) -> UnsafeVisitor<'tcx, 'a> {
         UnsafeVisitor {
             tcx: tcx,
+            crate_name: crate_name.to_string(),
+            detectors: HashMap::new(),
+            audited: HashSet::new(),
+            extended: false,
             node_info: None,
+            item_path: String::new(),
+            item_id: ast::CRATE_NODE_ID,
+            module_path: String::new(),
+            in_const: false,
+            in_drop: false,
+            external_trait: None,
+            raw_cast_operand: None,
+            static_mut_borrow: None,
+            roundtrip_operands: HashSet::new(),
+            ptr_ints: HashMap::new(),
+            test_fn: None,
+            in_test: false,
             unsafes: BTreeMap::new(),
+            foreign_items: Vec::new(),
+            exported_fns: Vec::new(),
+            exported_statics: Vec::new(),
+            fn_spans: HashMap::new(),
+            thin_wrappers: Vec::new(),
+            attrs: Vec::new(),
+            fuzz_targets: Vec::new(),
+            test_calls: Vec::new(),
+            exec_calls: Vec::new(),
+            unsafe_cell_gets: Vec::new(),
+            unsafe_trait_methods: Vec::new(),
+            unsafe_method_impls: Vec::new(),
+            target_feature_fns: Vec::new(),
+            static_muts: Vec::new(),
+            allocators: Vec::new(),
+            static_mut_escapes: Vec::new(),
         }
     }
 
@@ -104,6 +610,270 @@ impl<'tcx, 'a> UnsafeVisitor<'tcx, 'a> {
         &mut self.node_info.as_mut().unwrap().1
     }
 
+    /// Record the now-complete `info` for the unsafe fn or block `id`.
+    fn finish(&mut self, id: ast::NodeId, mut info: NodeInfo) {
+        // `cfg(miri)` has been stripped out of the expanded AST, so go
+        // back to the source to see if it was there.
+        if let Ok(snippet) = self.tcx.sess.codemap().span_to_snippet(info.span) {
+            for offset in find_cfg_miri(&snippet) {
+                let lo = info.span.lo + BytePos(offset as u32);
+                info.cfg_miri.push(mk_sp(lo, lo + BytePos(3)));
+            }
+        }
+        // blocks in an item are finished in source order.
+        let source = collapsed_source(self.tcx.sess.codemap(), info.span);
+        info.occurrence = self.unsafes.values().filter(|other| {
+            other.item_path == info.item_path && other.is_fn == info.is_fn &&
+                collapsed_source(self.tcx.sess.codemap(), other.span) == source
+        }).count();
+        assert!(self.unsafes.insert(id, info).is_none())
+    }
+
+    /// The function or method called by `expr`, if it is a call
+    /// that can be statically resolved.
+    fn callee(&self, expr: &ast::Expr) -> Option<ast::DefId> {
+        match expr.node {
+            ast::ExprCall(ref base, _) => {
+                match self.tcx.def_map.borrow().get(&base.id).map(|r| r.full_def()) {
+                    Some(def::DefFn(did, _)) | Some(def::DefMethod(did, ..)) => Some(did),
+                    _ => None
+                }
+            }
+            ast::ExprMethodCall(..) => {
+                match self.tcx.method_map.borrow().get(&MethodCall::expr(expr.id)) {
+                    Some(&ty::MethodCallee { origin: ty::MethodStatic(did), .. }) => Some(did),
+                    _ => None
+                }
+            }
+            _ => None
+        }
+    }
+
+    /// The path identifying `did` for the purposes of categorisation,
+    /// e.g. `core::ptr::read`, or `core::cell::UnsafeCell::get` for an
+    /// inherent method.
+    fn def_path(&self, did: ast::DefId) -> String {
+        let mut path = ty::item_path_str(self.tcx, did);
+        if let Some(impl_did) = ty::impl_of_method(self.tcx, did) {
+            let name = ty::impl_or_trait_item(self.tcx, did).name();
+            match ty::lookup_item_type(self.tcx, impl_did).ty.sty {
+                ty::ty_struct(s, _) | ty::ty_enum(s, _) => {
+                    path = format!("{}::{}", ty::item_path_str(self.tcx, s),
+                                   token::get_name(name))
+                }
+                _ => {}
+            }
+        }
+        // paths of external items start with their crate, but local
+        // ones don't.
+        if ast_util::is_local(did) {
+            path = format!("{}::{}", self.crate_name, path)
+        }
+        path
+    }
+
+    /// Record a call to `path` if it's in a user-defined category,
+    /// returning whether it was.
+    fn custom_call(&mut self, path: Option<&str>, span: Span) -> bool {
+        let category = match path.and_then(|p| self.detectors.get(p)) {
+            Some(c) => c.clone(),
+            None => return false
+        };
+        self.info().custom.entry(category).or_insert(Vec::new()).push(span);
+        true
+    }
+
+    /// Record a call to an unsafe function, in the most specific
+    /// category that applies.
+    fn unsafe_fn_call(&mut self, expr: &ast::Expr) {
+        self.info().needs_unsafe.push(expr.span);
+        let path = self.callee(expr).map(|did| self.def_path(did));
+        let target_feature = self.callee(expr).map_or(false, |did| {
+            ty::has_attr(self.tcx, did, "target_feature")
+        });
+        match path.as_ref().map(|p| &**p) {
+            Some(p) if self.audited.contains(p) => self.info().audited_calls.push(expr.span),
+            p if self.custom_call(p, expr.span) => {}
+            Some(p) if self.extended && EXTENDED_FNS.contains(&p) => {
+                self.info().extended.push(expr.span)
+            }
+            Some(p) if RAW_MEM_FNS.contains(&p) => self.info().raw_mem.push(expr.span),
+            Some(p) if RAW_SLICE_FNS.contains(&p) => self.info().raw_slice.push(expr.span),
+            Some(p) if RAW_PARTS_FNS.contains(&p) => self.info().raw_parts.push(expr.span),
+            Some(p) if ENV_FNS.contains(&p) => self.info().env.push(expr.span),
+            _ if target_feature => self.info().target_feature_call.push(expr.span),
+            Some(p) if HINT_UNCHECKED_FNS.contains(&p) => {
+                self.info().hint_unchecked.push(expr.span)
+            }
+            // `NonNull::new_unchecked`, `NonZeroU32::new_unchecked`,
+            // `Pin::new_unchecked`: a bad value here poisons every
+            // later safe use.
+            Some(p) if p.ends_with("::new_unchecked") => {
+                self.info().unchecked_constructor.push(expr.span)
+            }
+            // `downcast_ref_unchecked`, `from_utf8_unchecked`, ...:
+            // these assume something about the program's logic, not
+            // just its memory.
+            Some(p) if p.ends_with("_unchecked") => {
+                self.info().unchecked_assumption.push(expr.span)
+            }
+            _ => self.info().unsafe_call.push(expr.span),
+        }
+        self.note_crate(expr);
+    }
+
+    /// Record a call to a safe function, if it's one that is
+    /// interesting in an unsafe context.
+    fn safe_fn_call(&mut self, expr: &ast::Expr) {
+        let path = self.callee(expr).map(|did| self.def_path(did));
+        match path.as_ref().map(|p| &**p) {
+            p if self.custom_call(p, expr.span) => {}
+            Some(p) if self.extended && EXTENDED_FNS.contains(&p) => {
+                self.info().extended.push(expr.span)
+            }
+            Some(p) if ENV_FNS.contains(&p) => self.info().env.push(expr.span),
+            // the basis of every hand-rolled cell.
+            Some("core::cell::UnsafeCell::get") => {
+                self.info().unsafe_cell_get.push(expr.span)
+            }
+            _ => {}
+        }
+    }
+
+    /// Record the crate that the function called by `expr` comes from,
+    /// if it's not this one.
+    fn note_crate(&mut self, expr: &ast::Expr) {
+        if let Some(did) = self.callee(expr) {
+            if !ast_util::is_local(did) {
+                let name = self.tcx.sess.cstore.get_crate_data(did.krate).name.clone();
+                self.info().crates.insert(name);
+            }
+        }
+    }
+
+    /// The library named by `#[link(name = "...")]` on the `extern`
+    /// block containing the foreign item `id`, if any.
+    fn link_name(&self, id: ast::NodeId) -> Option<String> {
+        let extern_block = self.tcx.map.get_parent(id);
+        self.tcx.map.attrs(extern_block).iter()
+            .filter(|a| a.check_name("link"))
+            .filter_map(|a| a.meta_item_list())
+            .flat_map(|items| items.iter())
+            .find(|mi| mi.check_name("name"))
+            .and_then(|mi| mi.value_str())
+            .map(|name| name.to_string())
+    }
+
+    /// Check if `body` (of a function taking `decl`) is just `unsafe
+    /// { f(a, b, ...) }` with every argument one of the function's
+    /// parameters, i.e. the function doesn't do anything to
+    /// establish `f`'s preconditions. Must be called after `body` has
+    /// been visited.
+    fn is_thin_wrapper(&self, decl: &ast::FnDecl, body: &ast::Block) -> bool {
+        let inner = match (&*body.stmts, &body.expr) {
+            ([], &Some(ref e)) => match e.node {
+                ast::ExprBlock(ref b) if b.rules != ast::DefaultBlock => b,
+                _ => return false
+            },
+            _ => return false
+        };
+        let args = match (&*inner.stmts, &inner.expr) {
+            ([], &Some(ref e)) => match e.node {
+                ast::ExprCall(_, ref args) => &args[..],
+                ast::ExprMethodCall(_, _, ref args) => &args[..],
+                _ => return false
+            },
+            _ => return false
+        };
+
+        let params = decl.inputs.iter().filter_map(|arg| match arg.pat.node {
+            ast::PatIdent(_, ident, None) => Some(ident.node.name),
+            _ => None
+        }).collect::<Vec<_>>();
+        let forwarded = args.iter().all(|a| match a.node {
+            ast::ExprPath(None, ref p) if p.segments.len() == 1 && !p.global => {
+                params.contains(&p.segments[0].identifier.name)
+            }
+            _ => false
+        });
+
+        // the call is the only thing that needs the `unsafe`, whatever
+        // category it ended up in.
+        forwarded && match self.unsafes.get(&inner.id) {
+            Some(info) => info.needs_unsafe.len() == 1,
+            None => false
+        }
+    }
+
+    /// The pointer-to-int casts that the integer `expr` is computed
+    /// from, looking through arithmetic, integer casts and local
+    /// variables, e.g. `p as usize` in `(p as usize + off) as u64`.
+    fn ptr_int_sources(&self, expr: &ast::Expr) -> Vec<(ast::NodeId, Span)> {
+        match expr.node {
+            ast::ExprParen(ref e) | ast::ExprUnary(_, ref e) => self.ptr_int_sources(&**e),
+            ast::ExprBinary(_, ref l, ref r) => {
+                let mut sources = self.ptr_int_sources(&**l);
+                sources.extend(self.ptr_int_sources(&**r).into_iter());
+                sources
+            }
+            // `addr.wrapping_add(off)` and friends.
+            ast::ExprMethodCall(_, _, ref args) => {
+                match ty::expr_ty(self.tcx, expr).sty {
+                    ty::ty_uint(_) | ty::ty_int(_) => {
+                        args.iter().flat_map(|a| self.ptr_int_sources(&**a).into_iter())
+                            .collect()
+                    }
+                    _ => Vec::new()
+                }
+            }
+            ast::ExprCast(ref from, _) => {
+                match (&ty::expr_ty(self.tcx, from).sty, &ty::expr_ty(self.tcx, expr).sty) {
+                    (&ty::ty_ptr(_), &ty::ty_uint(_)) |
+                    (&ty::ty_ptr(_), &ty::ty_int(_)) => vec![(expr.id, expr.span)],
+                    (_, &ty::ty_uint(_)) | (_, &ty::ty_int(_)) => self.ptr_int_sources(&**from),
+                    _ => Vec::new()
+                }
+            }
+            ast::ExprPath(..) => {
+                match self.tcx.def_map.borrow().get(&expr.id).map(|d| d.full_def()) {
+                    Some(def::DefLocal(id)) => {
+                        self.ptr_ints.get(&id).cloned().into_iter().collect()
+                    }
+                    _ => Vec::new()
+                }
+            }
+            _ => Vec::new()
+        }
+    }
+
+    /// The local `static mut` that `expr` is the address of, looking
+    /// through `&`, casts and parentheses.
+    fn static_mut_address(&self, expr: &ast::Expr) -> Option<ast::NodeId> {
+        match expr.node {
+            ast::ExprParen(ref e) | ast::ExprCast(ref e, _) | ast::ExprAddrOf(_, ref e) => {
+                self.static_mut_address(&**e)
+            }
+            ast::ExprPath(..) => match ty::resolve_expr(self.tcx, expr) {
+                def::DefStatic(did, true) if ast_util::is_local(did) => Some(did.node),
+                _ => None
+            },
+            _ => None
+        }
+    }
+
+    /// The function item `expr` refers to, looking through casts and
+    /// parentheses, if any.
+    fn fn_item(&self, expr: &ast::Expr) -> Option<ast::DefId> {
+        match expr.node {
+            ast::ExprParen(ref e) | ast::ExprCast(ref e, _) => self.fn_item(&**e),
+            ast::ExprPath(..) => match ty::resolve_expr(self.tcx, expr) {
+                def::DefFn(did, _) | def::DefMethod(did, ..) => Some(did),
+                _ => None
+            },
+            _ => None
+        }
+    }
+
     fn check_ptr_cast(&mut self, span: Span, from: &ast::Expr, to: &ast::Expr) -> bool {
         let from_ty = ty::expr_ty(self.tcx, from);
         let to_ty = ty::expr_ty(self.tcx, to);
@@ -121,6 +891,47 @@ impl<'tcx, 'a> UnsafeVisitor<'tcx, 'a> {
                 true
             }
 
+            // code pointers turning into data pointers or integers, or
+            // vice versa.
+            (&ty::ty_bare_fn(..), &ty::ty_ptr(_)) |
+            (&ty::ty_bare_fn(..), &ty::ty_uint(_)) |
+            (&ty::ty_bare_fn(..), &ty::ty_int(_)) |
+            (&ty::ty_ptr(_), &ty::ty_bare_fn(..)) |
+            (&ty::ty_uint(_), &ty::ty_bare_fn(..)) |
+            (&ty::ty_int(_), &ty::ty_bare_fn(..)) => {
+                self.info().fn_ptr_cast.push(span);
+                true
+            }
+
+            // fabricating a pointer with no provenance.
+            (&ty::ty_uint(_), &ty::ty_ptr(_)) |
+            (&ty::ty_int(_), &ty::ty_ptr(_)) => {
+                let sources = self.ptr_int_sources(from);
+                if sources.is_empty() {
+                    self.info().int_to_ptr.push(span)
+                } else {
+                    self.info().provenance_roundtrip.push(span);
+                    for (id, cast_span) in sources {
+                        // via a variable, the first half was already
+                        // visited.
+                        self.info().ptr_to_int.retain(|s| *s != cast_span);
+                        self.roundtrip_operands.insert(id);
+                    }
+                }
+                true
+            }
+
+            // the address escaping the type system, e.g. to be
+            // laundered back into a pointer.
+            (&ty::ty_ptr(_), &ty::ty_uint(_)) |
+            (&ty::ty_ptr(_), &ty::ty_int(_)) => {
+                // the first half of a round-trip, already counted.
+                if !self.roundtrip_operands.contains(&to.id) {
+                    self.info().ptr_to_int.push(span);
+                }
+                true
+            }
+
             _ => {
                 false
             }
@@ -129,6 +940,198 @@ impl<'tcx, 'a> UnsafeVisitor<'tcx, 'a> {
 }
 
 impl<'tcx,'a,'b> Visitor<'a> for UnsafeVisitor<'tcx,'b> {
+    fn visit_item(&mut self, item: &'a ast::Item) {
+        let is_static = match item.node { ast::ItemStatic(..) => true, _ => false };
+        for a in item.attrs.iter() {
+            // these all control how the item appears to the linker, and
+            // so can make it alias with something else.
+            if a.check_name("link_section") || a.check_name("export_name") ||
+                (is_static && a.check_name("no_mangle")) {
+                self.attrs.push((item.span, a.name().to_string()))
+            }
+        }
+
+        if let ast::ItemStatic(ref ty, mutbl, _) = item.node {
+            if attr::contains_name(&item.attrs, "no_mangle") {
+                let mutbl = if mutbl == ast::MutMutable {"mut "} else {""};
+                let sig = format!("static {}{}: {}", mutbl, token::get_ident(item.ident),
+                                  pprust::ty_to_string(&**ty));
+                self.exported_statics.push((item.span, sig))
+            }
+        }
+
+        match item.node {
+            ast::ItemForeignMod(ref fm) => {
+                for fi in fm.items.iter() {
+                    self.foreign_items.push(ForeignItemInfo::new(&**fi, fm.abi))
+                }
+            }
+            ast::ItemStatic(ref ty, _, _)
+                if attr::contains_name(&item.attrs, "global_allocator") => {
+                    self.allocators.push((item.span, format!("#[global_allocator] static {}: {}",
+                                                             token::get_ident(item.ident),
+                                                             pprust::ty_to_string(&**ty))))
+                }
+            ast::ItemStatic(ref ty, ast::MutMutable, _) => {
+                let item_ty = ty::lookup_item_type(self.tcx, ast_util::local_def(item.id)).ty;
+                self.static_muts.push(StaticMutInfo {
+                    span: item.span,
+                    id: item.id,
+                    path: self.def_path(ast_util::local_def(item.id)),
+                    ty: pprust::ty_to_string(&**ty),
+                    needs_drop: ty::type_needs_drop(self.tcx, item_ty),
+                    has_refs: item_ty.walk().any(|t| match t.sty {
+                        ty::ty_rptr(..) => true,
+                        _ => false
+                    }),
+                })
+            }
+            ast::ItemFn(_, _, abi, _, _) => {
+                let no_mangle = attr::contains_name(&item.attrs, "no_mangle");
+                if no_mangle || abi != abi::Rust {
+                    self.exported_fns.push(ExportedFnInfo {
+                        span: item.span,
+                        name: token::get_ident(item.ident).to_string(),
+                        abi: abi,
+                        no_mangle: no_mangle,
+                    })
+                }
+            }
+            ast::ItemTrait(_, _, _, ref items) => {
+                let trait_path = self.def_path(ast_util::local_def(item.id));
+                for ti in items.iter() {
+                    match ti.node {
+                        ast::MethodTraitItem(ref sig, _)
+                            if sig.unsafety == ast::Unsafety::Unsafe => {
+                                self.unsafe_trait_methods.push(UnsafeMethodInfo {
+                                    span: ti.span,
+                                    trait_path: trait_path.clone(),
+                                    name: token::get_ident(ti.ident).to_string(),
+                                    self_ty: None,
+                                })
+                            }
+                        _ => {}
+                    }
+                }
+            }
+            ast::ItemImpl(_, _, _, Some(ref trait_ref), ref self_ty, ref items) => {
+                let def = self.tcx.def_map.borrow()[&trait_ref.ref_id].full_def();
+                let trait_path = self.def_path(def.def_id());
+                // every allocation in the program goes through this.
+                // Matched by name, since the path it's defined at isn't
+                // the one it's exported from (`core::alloc::global`).
+                if trait_path.rsplit("::").next() == Some("GlobalAlloc") {
+                    self.allocators.push((item.span, format!("impl GlobalAlloc for {}",
+                                                             pprust::ty_to_string(&**self_ty))))
+                }
+                for ii in items.iter() {
+                    match ii.node {
+                        ast::MethodImplItem(ref sig, _)
+                            if sig.unsafety == ast::Unsafety::Unsafe => {
+                                self.unsafe_method_impls.push(UnsafeMethodInfo {
+                                    span: ii.span,
+                                    trait_path: trait_path.clone(),
+                                    name: token::get_ident(ii.ident).to_string(),
+                                    self_ty: Some(pprust::ty_to_string(&**self_ty)),
+                                })
+                            }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+        let path = self.tcx.map.path_to_string(item.id);
+        let old_path = replace(&mut self.item_path, path.clone());
+        let old_id = replace(&mut self.item_id, item.id);
+        let module_path = match item.node {
+            ast::ItemMod(..) => path.clone(),
+            _ => self.module_path.clone()
+        };
+        let old_module_path = replace(&mut self.module_path, module_path);
+        let in_const = match item.node {
+            ast::ItemConst(..) | ast::ItemStatic(..) => true,
+            // items inside a constant (e.g. in a block) aren't
+            // themselves evaluated at compile time.
+            _ => false
+        };
+        let old_in_const = replace(&mut self.in_const, in_const);
+        let in_drop = match item.node {
+            ast::ItemImpl(_, _, _, Some(ref trait_ref), _, _) => {
+                let def = self.tcx.def_map.borrow()[&trait_ref.ref_id].full_def();
+                Some(def.def_id()) == self.tcx.lang_items.drop_trait()
+            }
+            _ => false
+        };
+        let old_in_drop = replace(&mut self.in_drop, in_drop);
+        let external_trait = match item.node {
+            // `Drop` is already called out.
+            ast::ItemImpl(_, _, _, Some(ref trait_ref), _, _) if !in_drop => {
+                let did = self.tcx.def_map.borrow()[&trait_ref.ref_id].full_def().def_id();
+                if ast_util::is_local(did) {
+                    None
+                } else {
+                    let krate = self.tcx.sess.cstore.get_crate_data(did.krate).name.clone();
+                    Some((self.def_path(did), krate))
+                }
+            }
+            _ => None
+        };
+        let old_external_trait = replace(&mut self.external_trait, external_trait);
+        // an unsafe context doesn't extend into the items defined
+        // inside it, e.g. a `static` in an `unsafe fn` is checked on
+        // its own, and anything in its initializer belongs to it.
+        let old_node_info = replace(&mut self.node_info, None);
+        let test_fn = match item.node {
+            ast::ItemFn(..) if attr::contains_name(&item.attrs, "test") => Some(path.clone()),
+            _ => None
+        };
+        let old_test_fn = replace(&mut self.test_fn, test_fn);
+        let in_test = self.in_test || attr::contains_name(&item.attrs, "test") ||
+            is_cfg_test(&item.attrs);
+        let old_in_test = replace(&mut self.in_test, in_test);
+        visit::walk_item(self, item);
+        self.in_test = old_in_test;
+        self.test_fn = old_test_fn;
+        self.node_info = old_node_info;
+        self.external_trait = old_external_trait;
+        self.in_drop = old_in_drop;
+        self.in_const = old_in_const;
+        self.item_id = old_id;
+        self.item_path = old_path;
+        self.module_path = old_module_path;
+
+        if let ast::ItemFn(ref decl, ast::Unsafety::Normal, _, ref generics, ref body) = item.node {
+            if item.vis == ast::Public && self.is_thin_wrapper(&**decl, &**body) {
+                self.thin_wrappers.push((item.span,
+                                         token::get_ident(item.ident).to_string()))
+            }
+
+            let has_unsafe = self.unsafes.values().any(|i| !i.compiler && i.item_path == path);
+            if has_unsafe && !generics.is_type_parameterized() {
+                let fuzzable = match ty::node_id_to_type(self.tcx, item.id).sty {
+                    ty::ty_bare_fn(_, ref f) => f.sig.0.inputs.iter().all(|t| type_is_fuzzable(t)),
+                    _ => false
+                };
+                if fuzzable {
+                    let args = decl.inputs.iter().map(|a| pprust::ty_to_string(&*a.ty)).collect();
+                    self.fuzz_targets.push((item.span, item.id, path, args))
+                }
+            }
+        }
+    }
+
+    fn visit_local(&mut self, local: &'a ast::Local) {
+        // remembered even outside unsafe code, where the round-trip
+        // often starts.
+        if let (&ast::PatIdent(_, _, None), Some(init)) = (&local.pat.node, local.init.as_ref()) {
+            if let Some(&source) = self.ptr_int_sources(&**init).first() {
+                self.ptr_ints.insert(local.pat.id, source);
+            }
+        }
+        visit::walk_local(self, local);
+    }
+
     fn visit_fn(&mut self, fn_kind: visit::FnKind<'a>, fn_decl: &'a ast::FnDecl,
                 block: &ast::Block, span: Span, node_id: ast::NodeId) {
         let (is_item_fn, is_unsafe_fn) = match fn_kind {
@@ -139,18 +1142,66 @@ impl<'tcx,'a,'b> Visitor<'a> for UnsafeVisitor<'tcx,'b> {
             _ => (false, false),
         };
 
+        if !is_item_fn {
+            // closures are part of whatever context they're written in,
+            // and unsafe blocks inside them are handled by visit_block.
+            if self.node_info.is_some() {
+                self.info().closures.push(span);
+            }
+            visit::walk_fn(self, fn_kind, fn_decl, block, span);
+            return
+        }
+
+        // methods aren't items, so they don't get a path from visit_item.
+        let old_path = match fn_kind {
+            visit::FkMethod(..) => {
+                let path = self.tcx.map.path_to_string(node_id);
+                Some((replace(&mut self.item_path, path), replace(&mut self.item_id, node_id)))
+            }
+            _ => None
+        };
+
+        self.fn_spans.insert(self.item_path.clone(), span);
+
+        // `#[target_feature(enable = "avx2,fma")]`, or the older
+        // `#[target_feature = "+avx2"]`.
+        let features = self.tcx.map.attrs(node_id).iter()
+            .filter(|a| a.check_name("target_feature"))
+            .flat_map(|a| {
+                let mut values = a.value_str().into_iter().collect::<Vec<_>>();
+                if let Some(items) = a.meta_item_list() {
+                    values.extend(items.iter()
+                                  .filter(|mi| mi.check_name("enable"))
+                                  .filter_map(|mi| mi.value_str()));
+                }
+                values.into_iter()
+            })
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>();
+        if !features.is_empty() {
+            self.target_feature_fns.push((span, self.item_path.clone(), features))
+        }
+
         let old_node_info = if is_unsafe_fn {
-            replace(&mut self.node_info, Some((node_id, NodeInfo::new(span, true, false))))
-        } else if is_item_fn {
-            replace(&mut self.node_info, None)
+            let mut info = NodeInfo::new(span, true, false, self.item_path.clone());
+            info.in_drop = self.in_drop;
+            info.in_test = self.in_test;
+            info.item_id = self.item_id;
+            info.module_path = self.module_path.clone();
+            info.external_trait = self.external_trait.clone();
+            replace(&mut self.node_info, Some((node_id, info)))
         } else {
-            None
+            replace(&mut self.node_info, None)
         };
         visit::walk_fn(self, fn_kind, fn_decl, block, span);
 
+        if let Some((path, id)) = old_path {
+            self.item_path = path;
+            self.item_id = id;
+        }
+
         match replace(&mut self.node_info, old_node_info) {
-            Some((id, info)) => assert!(self.unsafes.insert(id, info).is_none()),
-            //Some((id, info)) => { self.unsafes.insert(id, info); }
+            Some((id, info)) => self.finish(id, info),
             None => {}
         }
     }
@@ -161,10 +1212,17 @@ impl<'tcx,'a,'b> Visitor<'a> for UnsafeVisitor<'tcx,'b> {
             ast::UnsafeBlock(source) => {
                 let compiler = source == ast::CompilerGenerated;
                 if self.node_info.is_none() || compiler {
-                    (replace(&mut self.node_info,
-                             Some((block.id, NodeInfo::new(block.span, false, compiler)))),
-                     true)
+                    let mut info = NodeInfo::new(block.span, false, compiler,
+                                                 self.item_path.clone());
+                    info.in_const = self.in_const;
+                    info.in_drop = self.in_drop;
+                    info.in_test = self.in_test;
+                    info.item_id = self.item_id;
+                    info.module_path = self.module_path.clone();
+                    info.external_trait = self.external_trait.clone();
+                    (replace(&mut self.node_info, Some((block.id, info))), true)
                 } else {
+                    self.info().nested.push(block.span);
                     (None, false)
                 }
             }
@@ -173,55 +1231,121 @@ impl<'tcx,'a,'b> Visitor<'a> for UnsafeVisitor<'tcx,'b> {
 
         if inserted {
             match replace(&mut self.node_info, old_node_info) {
-                Some((id, info)) => assert!(self.unsafes.insert(id, info).is_none()),
-                //Some((id, info)) => { self.unsafes.insert(id, info); }
+                Some((id, info)) => self.finish(id, info),
                 None => {}
             }
         }
     }
 
     fn visit_expr(&mut self, expr: &'a ast::Expr) {
+        if let Some(did) = self.callee(expr) {
+            let path = self.def_path(did);
+            let foreign = path.starts_with("libc::") || (ast_util::is_local(did) && {
+                match self.tcx.map.find(did.node) {
+                    Some(ast_map::NodeForeignItem(_)) => true,
+                    _ => false
+                }
+            });
+            if self.node_info.is_none() && path == "core::cell::UnsafeCell::get" {
+                self.unsafe_cell_gets.push((expr.span, self.item_path.clone()))
+            }
+            if is_exec(&path, foreign) {
+                self.exec_calls.push((expr.span, path))
+            }
+        }
+
+        if let Some(test) = self.test_fn.clone() {
+            match self.callee(expr) {
+                Some(did) if ast_util::is_local(did) => {
+                    let callee = self.tcx.map.path_to_string(did.node);
+                    self.test_calls.push((test, callee))
+                }
+                _ => {}
+            }
+        }
+
         if self.node_info.is_some() {
             match expr.node {
                 ast::ExprMethodCall(_, _, _) => {
                     let method_call = MethodCall::expr(expr.id);
-                    let base_type = self.tcx.method_map.borrow()[&method_call].ty;
+                    let (base_type, is_dyn) = {
+                        let method_map = self.tcx.method_map.borrow();
+                        let callee = &method_map[&method_call];
+                        (callee.ty, match callee.origin {
+                            ty::MethodTraitObject(..) => true,
+                            _ => false
+                        })
+                    };
                     if type_is_unsafe_function(base_type) {
-                        self.info().unsafe_call.push(expr.span)
+                        if is_dyn {
+                            self.info().dyn_call.push(expr.span)
+                        }
+                        self.unsafe_fn_call(expr)
+                    } else {
+                        self.safe_fn_call(expr)
                     }
                 }
                 ast::ExprCall(ref base, ref args) => {
-                    match (&base.node, &**args) {
-                        (&ast::ExprPath(_, ref p), [ref arg])
-                            // ew, but whatever.
-                            if p.segments.last().unwrap().identifier.name ==
-                            token::intern("transmute") => {
-                                if !self.check_ptr_cast(expr.span, &**arg, expr) {
-                                    // not a */& -> *mut/&mut cast.
-                                    self.info().transmute.push(expr.span)
-                                }
+                    let is_transmute = self.callee(expr).map_or(false, |did| {
+                        self.def_path(did) == "core::intrinsics::transmute"
+                    });
+                    match &**args {
+                        // `mem::transmute` is a reexport, so however it's
+                        // named, it resolves to the intrinsic.
+                        [ref arg] if is_transmute => {
+                            self.info().needs_unsafe.push(expr.span);
+                            if !self.check_ptr_cast(expr.span, &**arg, expr) {
+                                // not a */& -> *mut/&mut cast.
+                                self.info().transmute.push(expr.span)
                             }
+                        }
 
                         _ => {
-                            let is_ffi = match self.tcx.def_map.borrow().get(&base.id) {
-                                Some(&def::PathResolution { base_def: def::DefFn(did, _), .. }) => {
+                            let ffi_item = match self.tcx.def_map.borrow().get(&base.id) {
+                                Some(&def::PathResolution { base_def: def::DefFn(did, _), .. })
                                     // cross-crate calls are always
                                     // just unsafe calls.
-                                    ast_util::is_local(did) &&
+                                    if ast_util::is_local(did) => {
                                         match self.tcx.map.get(did.node) {
-                                            ast_map::NodeForeignItem(_) => true,
-                                            _ => false
+                                            ast_map::NodeForeignItem(fi) => Some((did.node, fi.ident)),
+                                            _ => None
                                         }
-                                }
-                                _ => false
+                                    }
+                                _ => None
                             };
 
-                            if is_ffi {
-                                self.info().ffi.push(expr.span)
+                            if let Some((id, ident)) = ffi_item {
+                                let mut callbacks = Vec::new();
+                                for arg in args.iter() {
+                                    if let Some(did) = self.fn_item(&**arg) {
+                                        let abi = match ty::lookup_item_type(self.tcx, did).ty.sty {
+                                            ty::ty_bare_fn(_, ref f) => f.abi,
+                                            _ => abi::Rust
+                                        };
+                                        self.info().callback.push(arg.span);
+                                        callbacks.push((self.def_path(did), abi));
+                                    }
+                                }
+                                let call = FfiCall {
+                                    span: expr.span,
+                                    name: token::get_ident(ident).to_string(),
+                                    lib: self.link_name(id),
+                                    callbacks: callbacks,
+                                };
+                                self.info().ffi.push(expr.span);
+                                self.info().needs_unsafe.push(expr.span);
+                                self.info().ffi_calls.push(call);
+                                for arg in args.iter() {
+                                    if let Some(id) = self.static_mut_address(&**arg) {
+                                        self.static_mut_escapes.push((id, expr.span))
+                                    }
+                                }
                             } else {
                                 let base_type = ty::node_id_to_type(self.tcx, base.id);
                                 if type_is_unsafe_function(base_type) {
-                                    self.info().unsafe_call.push(expr.span)
+                                    self.unsafe_fn_call(expr)
+                                } else {
+                                    self.safe_fn_call(expr)
                                 }
                             }
                         }
@@ -232,25 +1356,81 @@ impl<'tcx,'a,'b> Visitor<'a> for UnsafeVisitor<'tcx,'b> {
                     let base_type = ty::node_id_to_type(self.tcx, base.id);
                     match base_type.sty {
                         ty::ty_ptr(_) => {
-                            self.info().raw_deref.push(expr.span)
+                            self.info().raw_deref.push(expr.span);
+                            self.info().needs_unsafe.push(expr.span)
                         }
                         _ => {}
                     }
                 }
-                ast::ExprInlineAsm(..) => {
-                    self.info().asm.push(expr.span)
+                ast::ExprInlineAsm(ref ia) => {
+                    // `+` (read-write) and memory outputs, and clobbering
+                    // "memory", all mean the asm can write to memory that
+                    // Rust code can see; anything else just computes
+                    // values into registers.
+                    let writes_memory = ia.outputs.iter().any(|&(ref constraint, _, is_rw)| {
+                        is_rw || constraint.contains('m')
+                    }) || ia.clobbers.iter().any(|c| &**c == "memory");
+                    if writes_memory {
+                        self.info().asm_mem.push(expr.span)
+                    } else {
+                        self.info().asm.push(expr.span)
+                    }
+                    self.info().needs_unsafe.push(expr.span);
+
+                    let mut options = Vec::new();
+                    if ia.volatile { options.push("volatile") }
+                    if ia.alignstack { options.push("alignstack") }
+                    if ia.dialect == ast::AsmIntel { options.push("intel") }
+                    let clobbers = ia.clobbers.iter().map(|c| format!("\"{}\"", c))
+                        .collect::<Vec<_>>();
+                    let summary = format!("clobbers: {}; options: {}",
+                                          if clobbers.is_empty() {
+                                              "none".to_string()
+                                          } else {
+                                              clobbers.connect(", ")
+                                          },
+                                          if options.is_empty() {
+                                              "none".to_string()
+                                          } else {
+                                              options.connect(", ")
+                                          });
+                    self.info().asm_details.push((expr.span, summary))
                 }
                 ast::ExprPath(..) => {
                     match ty::resolve_expr(self.tcx, expr) {
-                        def::DefStatic(_, true) => {
-                            self.info().static_mut.push(expr.span)
+                        // already counted as a reference.
+                        def::DefStatic(_, true) if self.static_mut_borrow != Some(expr.id) => {
+                            self.info().static_mut.push(expr.span);
+                            self.info().needs_unsafe.push(expr.span)
                         }
                         _ => {}
                     }
                 }
                 ast::ExprCast(ref from, _) => {
+                    if let ty::ty_ptr(_) = ty::expr_ty(self.tcx, expr).sty {
+                        self.raw_cast_operand = Some(from.id);
+                    }
                     self.check_ptr_cast(expr.span, &**from, expr);
                 }
+                ast::ExprAddrOf(_, ref inner) => {
+                    // `&place as *const _`, or `&place` coerced to a raw
+                    // pointer, is how one gets a raw pointer to a place.
+                    let coerced = match self.tcx.adjustments.borrow().get(&expr.id) {
+                        Some(&ty::AdjustDerefRef(ty::AutoDerefRef {
+                            autoref: Some(ty::AutoUnsafe(..)), ..
+                        })) => true,
+                        _ => false
+                    };
+                    if coerced || self.raw_cast_operand == Some(expr.id) {
+                        self.info().raw_borrow.push(expr.span)
+                    } else if let ast::ExprPath(..) = inner.node {
+                        if let def::DefStatic(_, true) = ty::resolve_expr(self.tcx, inner) {
+                            self.info().static_mut_ref.push(expr.span);
+                            self.info().needs_unsafe.push(expr.span);
+                            self.static_mut_borrow = Some(inner.id);
+                        }
+                    }
+                }
                 _ => {}
             }
         }
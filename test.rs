@@ -25,3 +25,20 @@ fn main() {
         abort()
     }
 }
+trait Raw {
+    unsafe fn poke(&self);
+}
+impl Raw for u8 {
+    unsafe fn poke(&self) {}
+}
+fn poke_dyn(r: &Raw) {
+    unsafe {
+        r.poke()
+    }
+}
+fn set_env() {
+    unsafe {
+        std::env::set_var("KEY", "value");
+        std::env::remove_var("KEY");
+    }
+}